@@ -0,0 +1,195 @@
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store::{self, ContentRef};
+use crate::state::ChatState;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{to_vec, Value};
+use std::collections::HashSet;
+
+/// An entry in one of the append-only logs, linked to the entry before it
+pub trait LogEntry: Serialize + DeserializeOwned {
+    fn timestamp(&self) -> u64;
+    fn previous(&self) -> Option<String>;
+    fn set_previous(&mut self, previous: Option<String>);
+}
+
+/// One tool invocation, as recorded in the audit log
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ToolAuditEntry {
+    /// `timing::now()` when the call started
+    pub timestamp: u64,
+    pub tool: String,
+
+    /// Server that handled the call, or `chat-state` for built-in tools
+    pub server: Option<String>,
+    pub args: Value,
+
+    /// Size in bytes of the serialized result content, before any truncation
+    pub result_size: usize,
+    pub is_error: bool,
+    pub duration_ms: u64,
+
+    /// How the call was approved. There is no approval step yet, so this is always `auto`.
+    pub approval: String,
+
+    /// Whether the result came from the tool cache
+    pub cached: bool,
+
+    /// Completion message that requested the call
+    pub request_message_id: Option<String>,
+
+    /// Id of the tool use block. The ToolResult with this id, in the message
+    /// after `request_message_id`, carried the result back to the model.
+    #[serde(default)]
+    pub tool_use_id: String,
+
+    /// Whether `args` was cleared because the message that made the call was redacted
+    #[serde(default)]
+    pub redacted: bool,
+
+    /// Previous entry in the log
+    pub previous: Option<String>,
+}
+
+impl LogEntry for ToolAuditEntry {
+    fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    fn previous(&self) -> Option<String> {
+        self.previous.clone()
+    }
+
+    fn set_previous(&mut self, previous: Option<String>) {
+        self.previous = previous;
+    }
+}
+
+/// Store entries as a chain after `previous`, returning the newest entry's hash
+fn store_chain<T: LogEntry>(
+    store_id: &str,
+    mut previous: Option<String>,
+    entries: Vec<T>,
+) -> Result<Option<String>, String> {
+    for mut entry in entries {
+        entry.set_previous(previous.clone());
+        let entry_bytes =
+            to_vec(&entry).map_err(|e| format!("Failed to serialize log entry: {}", e))?;
+        let entry_ref = store::store(store_id, &entry_bytes)
+            .map_err(|e| format!("Failed to store log entry: {}", e))?;
+        previous = Some(entry_ref.hash);
+    }
+    Ok(previous)
+}
+
+/// Append entries to a log. Each entry is stored as its own blob linking to
+/// the previous one, and the label points at the newest.
+pub fn append_log<T: LogEntry>(store_id: &str, label: &str, entries: Vec<T>) -> Result<(), String> {
+    let head = store::get_by_label(store_id, label)
+        .map_err(|e| format!("Failed to read {} head: {}", label, e))?;
+    let is_new_log = head.is_none();
+    let previous = store_chain(store_id, head.map(|r| r.hash), entries)?;
+
+    if let Some(hash) = previous {
+        let head = ContentRef { hash };
+        let result = if is_new_log {
+            store::label(store_id, label, &head)
+        } else {
+            store::replace_at_label(store_id, label, &head)
+        };
+        result.map_err(|e| format!("Failed to update {} head: {}", label, e))?;
+    }
+
+    Ok(())
+}
+
+/// Replace every entry of an existing log, oldest first. The old chain stays
+/// in the store, since it has no delete, but the label no longer reaches it.
+pub fn rewrite_log<T: LogEntry>(
+    store_id: &str,
+    label: &str,
+    entries: Vec<T>,
+) -> Result<(), String> {
+    match store_chain(store_id, None, entries)? {
+        Some(hash) => store::replace_at_label(store_id, label, &ContentRef { hash })
+            .map_err(|e| format!("Failed to update {} head: {}", label, e)),
+        None => store::remove_label(store_id, label)
+            .map_err(|e| format!("Failed to remove {}: {}", label, e)),
+    }
+}
+
+/// Log entries at or after `since`, oldest first
+pub fn read_log<T: LogEntry>(
+    store_id: &str,
+    label: &str,
+    since: Option<u64>,
+) -> Result<Vec<T>, String> {
+    let mut entries = Vec::new();
+    let mut current = store::get_by_label(store_id, label)
+        .map_err(|e| format!("Failed to read {} head: {}", label, e))?;
+
+    while let Some(entry_ref) = current {
+        let entry_bytes = store::get(store_id, &entry_ref)
+            .map_err(|e| format!("Failed to read log entry: {}", e))?;
+        let entry: T = serde_json::from_slice(&entry_bytes)
+            .map_err(|e| format!("Failed to parse log entry: {}", e))?;
+
+        if since.is_some_and(|since| entry.timestamp() < since) {
+            break;
+        }
+
+        current = entry.previous().map(|hash| ContentRef { hash });
+        entries.push(entry);
+    }
+
+    entries.reverse();
+    Ok(entries)
+}
+
+impl ChatState {
+    fn tool_audit_label(&self) -> String {
+        format!("tool_audit_{}", self.conversation_id)
+    }
+
+    pub fn append_tool_audit(&self, entries: Vec<ToolAuditEntry>) -> Result<(), String> {
+        append_log(&self.store_id, &self.tool_audit_label(), entries)
+    }
+
+    /// Audit entries at or after `since`, optionally for one tool, oldest first
+    pub fn get_tool_audit(
+        &self,
+        since: Option<u64>,
+        tool: Option<String>,
+    ) -> Result<Vec<ToolAuditEntry>, String> {
+        log("Reading tool audit log");
+
+        let mut entries: Vec<ToolAuditEntry> =
+            read_log(&self.store_id, &self.tool_audit_label(), since)?;
+        if let Some(tool) = tool {
+            entries.retain(|entry| entry.tool == tool);
+        }
+        Ok(entries)
+    }
+
+    /// Clear the arguments of audited calls with the given tool use ids,
+    /// returning how many entries changed
+    pub fn redact_tool_audit(&self, tool_use_ids: &HashSet<String>) -> Result<usize, String> {
+        let label = self.tool_audit_label();
+        let mut entries: Vec<ToolAuditEntry> = read_log(&self.store_id, &label, None)?;
+
+        let mut redacted = 0;
+        for entry in entries
+            .iter_mut()
+            .filter(|entry| tool_use_ids.contains(&entry.tool_use_id))
+        {
+            entry.args = Value::Object(Default::default());
+            entry.redacted = true;
+            redacted += 1;
+        }
+
+        if redacted > 0 {
+            rewrite_log(&self.store_id, &label, entries)?;
+        }
+        Ok(redacted)
+    }
+}
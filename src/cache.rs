@@ -0,0 +1,133 @@
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::timing;
+use crate::state::ChatState;
+use mcp_protocol::tool::ToolContent;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// A tool result kept for reuse by identical calls
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CachedToolResult {
+    pub content: Vec<ToolContent>,
+
+    /// `timing::now()` after which the entry is stale
+    pub expires_at: u64,
+}
+
+/// Cache key for a tool call: the tool name plus its arguments with object keys sorted
+pub fn cache_key(tool: &str, args: &Value) -> String {
+    format!("{}:{}", tool, canonical_json(args))
+}
+
+fn canonical_json(value: &Value) -> String {
+    match value {
+        Value::Object(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            let fields: Vec<String> = keys
+                .into_iter()
+                .map(|k| format!("{}:{}", Value::String(k.clone()), canonical_json(&map[k])))
+                .collect();
+            format!("{{{}}}", fields.join(","))
+        }
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(canonical_json).collect();
+            format!("[{}]", items.join(","))
+        }
+        other => other.to_string(),
+    }
+}
+
+impl ChatState {
+    /// Cache TTL configured for a tool, if its server opted in to caching
+    pub fn tool_cache_ttl(&self, tool: &str) -> Option<u64> {
+        self.settings
+            .mcp_servers
+            .iter()
+            .find(|mcp| mcp.has_tool(tool))
+            .and_then(|mcp| mcp.cached_tools.as_ref())
+            .and_then(|cached| cached.get(tool).copied())
+    }
+
+    /// Look up a fresh cached result for a tool call
+    pub fn cached_tool_result(&mut self, tool: &str, args: &Value) -> Option<Vec<ToolContent>> {
+        self.tool_cache_ttl(tool)?;
+
+        let key = cache_key(tool, args);
+        let now = timing::now();
+        match self.tool_cache.get(&key) {
+            Some(entry) if entry.expires_at > now => {
+                log(&format!("Tool cache hit: {}", key));
+                Some(entry.content.clone())
+            }
+            Some(_) => {
+                log(&format!("Tool cache entry expired: {}", key));
+                self.tool_cache.remove(&key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Remember a successful tool result if the tool is cacheable
+    pub fn cache_tool_result(&mut self, tool: &str, args: &Value, content: &[ToolContent]) {
+        let ttl = match self.tool_cache_ttl(tool) {
+            Some(ttl) => ttl,
+            None => return,
+        };
+
+        let now = timing::now();
+        self.tool_cache.retain(|_, entry| entry.expires_at > now);
+        self.tool_cache.insert(
+            cache_key(tool, args),
+            CachedToolResult {
+                content: content.to_vec(),
+                expires_at: now.saturating_add(ttl),
+            },
+        );
+    }
+
+    pub fn clear_tool_cache(&mut self) {
+        log(&format!(
+            "Clearing tool cache ({} entries)",
+            self.tool_cache.len()
+        ));
+        self.tool_cache.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cache_key, canonical_json};
+    use serde_json::json;
+
+    #[test]
+    fn sorts_object_keys_at_every_depth() {
+        assert_eq!(
+            canonical_json(&json!({"b": 1, "a": {"d": [2, {"f": 3, "e": 4}], "c": null}})),
+            r#"{"a":{"c":null,"d":[2,{"e":4,"f":3}]},"b":1}"#
+        );
+    }
+
+    #[test]
+    fn keeps_array_order_and_escapes_strings() {
+        assert_eq!(canonical_json(&json!([3, 1, 2])), "[3,1,2]");
+        assert_eq!(canonical_json(&json!({"k\"": "v\n"})), r#"{"k\"":"v\n"}"#);
+    }
+
+    #[test]
+    fn same_arguments_share_a_key() {
+        assert_eq!(
+            cache_key("search", &json!({"query": "x", "limit": 5})),
+            cache_key("search", &json!({"limit": 5, "query": "x"}))
+        );
+        assert_ne!(
+            cache_key("search", &json!({"query": "x"})),
+            cache_key("fetch", &json!({"query": "x"}))
+        );
+        assert_ne!(
+            cache_key("search", &json!({"query": "x"})),
+            cache_key("search", &json!({"query": "y"}))
+        );
+    }
+}
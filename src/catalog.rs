@@ -0,0 +1,200 @@
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::timing;
+use crate::state::{ChatState, ConversationSettings, ModelConfig};
+use crate::validation::SettingsErrors;
+use genai_types::{ModelInfo, ProxyRequest, ProxyResponse};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// How long a provider's model list is reused before asking its proxy again
+pub const MODEL_CATALOG_TTL_MS: u64 = 60 * 60 * 1000;
+
+/// Models reported by one provider
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CachedModels {
+    pub models: Vec<ModelInfo>,
+
+    /// `timing::now()` when the list was fetched
+    pub fetched_at: u64,
+}
+
+impl ChatState {
+    fn fetch_provider_models(&mut self, provider: &str) -> Result<Vec<ModelInfo>, String> {
+        let proxy = self
+            .proxies
+            .get_mut(provider)
+            .ok_or_else(|| format!("Provider {} not found", provider))?;
+
+        match proxy.send_to_proxy(ProxyRequest::ListModels)? {
+            ProxyResponse::ListModels { models } => {
+                self.model_catalog.insert(
+                    provider.to_string(),
+                    CachedModels {
+                        models: models.clone(),
+                        fetched_at: timing::now(),
+                    },
+                );
+                Ok(models)
+            }
+            ProxyResponse::Error { error } => Err(format!("Error from proxy: {}", error)),
+            _ => Err("Unexpected response from proxy".to_string()),
+        }
+    }
+
+    /// Models for one provider, from the catalog while it is fresh
+    pub fn provider_models(&mut self, provider: &str) -> Result<Vec<ModelInfo>, String> {
+        if let Some(cached) = self.model_catalog.get(provider) {
+            if timing::now().saturating_sub(cached.fetched_at) < MODEL_CATALOG_TTL_MS {
+                return Ok(cached.models.clone());
+            }
+        }
+
+        self.fetch_provider_models(provider)
+    }
+
+    /// Models from every provider, with the error for each provider that could
+    /// not be listed. A stale catalog entry is used when a refresh fails.
+    pub fn list_models(&mut self) -> Result<(Vec<ModelInfo>, HashMap<String, String>), String> {
+        log("Getting model list from proxies");
+
+        let mut providers: Vec<String> = self.proxies.keys().cloned().collect();
+        providers.sort();
+
+        let mut models = Vec::new();
+        let mut errors = HashMap::new();
+        for provider in providers {
+            match self.provider_models(&provider) {
+                Ok(m) => models.extend(m),
+                Err(e) => {
+                    log(&format!("Error getting model list from {}: {}", provider, e));
+                    if let Some(cached) = self.model_catalog.get(&provider) {
+                        models.extend(cached.models.clone());
+                    }
+                    errors.insert(provider, e);
+                }
+            }
+        }
+
+        if models.is_empty() {
+            log("No models found");
+            let mut details: Vec<String> = errors
+                .iter()
+                .map(|(provider, e)| format!("{}: {}", provider, e))
+                .collect();
+            details.sort();
+            return Err(format!("No models found: {}", details.join("; ")));
+        }
+
+        log(&format!("Found {} models", models.len()));
+        Ok((models, errors))
+    }
+
+    /// Catalog entry for a model, listing its provider's models on a miss
+    pub fn model_info(&mut self, model_config: &ModelConfig) -> Option<ModelInfo> {
+        match self.provider_models(&model_config.provider) {
+            Ok(models) => models.into_iter().find(|m| m.id == model_config.model),
+            Err(e) => {
+                log(&format!(
+                    "Failed to list models for {}: {}",
+                    model_config.provider, e
+                ));
+                None
+            }
+        }
+    }
+
+    /// A provider's models, or None when they cannot be checked: the
+    /// provider's models cannot be listed, or `settings` replace the provider
+    /// and the current proxy would answer for the old one.
+    fn models_to_check(
+        &mut self,
+        settings: &ConversationSettings,
+        provider_name: &str,
+    ) -> Option<Vec<ModelInfo>> {
+        let provider = settings.providers.iter().find(|p| p.name == provider_name);
+        let proxy_matches = match (self.proxies.get(provider_name), provider) {
+            (Some(proxy), Some(provider)) => proxy.matches_config(provider),
+            _ => false,
+        };
+        if !proxy_matches {
+            log(&format!(
+                "Provider {} has no proxy for these settings yet, skipping model check",
+                provider_name
+            ));
+            return None;
+        }
+
+        match self.provider_models(provider_name) {
+            Ok(models) => Some(models),
+            Err(e) => {
+                log(&format!(
+                    "Failed to list models for {}, skipping model check: {}",
+                    provider_name, e
+                ));
+                None
+            }
+        }
+    }
+
+    /// Reject every model its provider does not list, by field, and clamp
+    /// `max_tokens` to the primary model's limit. Providers whose models
+    /// cannot be checked are skipped.
+    pub fn check_models_against_catalog(
+        &mut self,
+        settings: &mut ConversationSettings,
+    ) -> Result<(), SettingsErrors> {
+        let mut fields = vec![("model_config".to_string(), settings.model_config.clone())];
+        for (i, model_config) in settings.fallback_models.iter().enumerate() {
+            fields.push((format!("fallback_models[{}]", i), model_config.clone()));
+        }
+        if let Some(title_model) = &settings.title_model {
+            fields.push(("title_model".to_string(), title_model.clone()));
+        }
+        for (i, rule) in settings.routing_rules.iter().enumerate() {
+            fields.push((
+                format!("routing_rules[{}].model_config", i),
+                rule.model_config.clone(),
+            ));
+        }
+
+        let mut catalogs: HashMap<String, Option<Vec<ModelInfo>>> = HashMap::new();
+        let mut errors = SettingsErrors::new();
+        for (i, (path, model_config)) in fields.iter().enumerate() {
+            if !catalogs.contains_key(&model_config.provider) {
+                let models = self.models_to_check(settings, &model_config.provider);
+                catalogs.insert(model_config.provider.clone(), models);
+            }
+            let models = match &catalogs[&model_config.provider] {
+                Some(models) => models,
+                None => continue,
+            };
+
+            match models.iter().find(|m| m.id == model_config.model) {
+                None => {
+                    errors.insert(
+                        format!("{}.model", path),
+                        format!(
+                            "unknown model {} for provider {}",
+                            model_config.model, model_config.provider
+                        ),
+                    );
+                }
+                // The primary model is listed first
+                Some(model) if i == 0 && settings.max_tokens > model.max_tokens => {
+                    log(&format!(
+                        "Clamping max_tokens from {} to {} for {}",
+                        settings.max_tokens, model.max_tokens, model.id
+                    ));
+                    settings.max_tokens = model.max_tokens;
+                }
+                Some(_) => {}
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
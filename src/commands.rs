@@ -0,0 +1,431 @@
+use crate::bindings::theater::simple::runtime::log;
+use crate::state::{
+    ChatEntry, ChatMessage, ChatState, ConversationSettings, LocalEntry, ModelConfig,
+};
+use crate::tools::render_entry;
+use crate::validation::apply_settings;
+use genai_types::messages::Role;
+use genai_types::{Message, MessageContent};
+
+/// Runs a slash command with the text after its name, returning the reply
+type CommandHandler = fn(&mut ChatState, &str) -> Result<String, String>;
+
+pub struct SlashCommand {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub description: &'static str,
+    pub handler: CommandHandler,
+}
+
+/// Commands handled locally when `settings.slash_commands` is on
+pub const COMMANDS: &[SlashCommand] = &[
+    SlashCommand {
+        name: "model",
+        usage: "/model [provider/]model",
+        description: "Show or change the model",
+        handler: model_command,
+    },
+    SlashCommand {
+        name: "system",
+        usage: "/system [prompt | --clear]",
+        description: "Show, set or clear the system prompt",
+        handler: system_command,
+    },
+    SlashCommand {
+        name: "temperature",
+        usage: "/temperature [value | default]",
+        description: "Show or change the temperature",
+        handler: temperature_command,
+    },
+    SlashCommand {
+        name: "branch",
+        usage: "/branch [message id or prefix]",
+        description: "Show the head, or move it to another message",
+        handler: branch_command,
+    },
+    SlashCommand {
+        name: "undo",
+        usage: "/undo",
+        description: "Move the head back to before the last user message",
+        handler: undo_command,
+    },
+    SlashCommand {
+        name: "tools",
+        usage: "/tools",
+        description: "List the tools available to the model",
+        handler: tools_command,
+    },
+    SlashCommand {
+        name: "export",
+        usage: "/export",
+        description: "Render the conversation as Markdown",
+        handler: export_command,
+    },
+    SlashCommand {
+        name: "help",
+        usage: "/help",
+        description: "List the available commands",
+        handler: help_command,
+    },
+];
+
+/// Command text of a user message starting with `/`. A leading `//` is not a command.
+fn command_text(message: &Message) -> Option<String> {
+    if !matches!(message.role, Role::User) {
+        return None;
+    }
+
+    let mut text = String::new();
+    for content in &message.content {
+        match content {
+            MessageContent::Text { text: t } => text.push_str(t),
+            _ => return None,
+        }
+    }
+
+    let text = text.trim();
+    if text.starts_with('/') && !text.starts_with("//") {
+        Some(text.to_string())
+    } else {
+        None
+    }
+}
+
+/// Command name without the `/`, and the trimmed text after it
+fn split_command(text: &str) -> (&str, &str) {
+    let (name, args) = match text.split_once(char::is_whitespace) {
+        Some((name, args)) => (name, args.trim()),
+        None => (text, ""),
+    };
+    (name.trim_start_matches('/'), args)
+}
+
+/// The one id starting with `prefix`, or None if no id does
+fn match_prefix<'a>(
+    ids: impl Iterator<Item = &'a String>,
+    prefix: &str,
+) -> Result<Option<String>, String> {
+    let matches: Vec<&String> = ids.filter(|id| id.starts_with(prefix)).collect();
+    match matches.as_slice() {
+        [] => Ok(None),
+        [id] => Ok(Some((*id).clone())),
+        _ => Err(format!("{} matches {} messages", prefix, matches.len())),
+    }
+}
+
+/// Parent of the last user text message in `chain`, or None if there is no
+/// such message. Tool results are user messages too, but not typed by the user.
+fn undo_target(chain: &[ChatMessage]) -> Option<Option<String>> {
+    chain
+        .iter()
+        .rev()
+        .find(|m| match &m.entry {
+            ChatEntry::Message(message) => {
+                matches!(message.role, Role::User)
+                    && message
+                        .content
+                        .iter()
+                        .any(|c| matches!(c, MessageContent::Text { .. }))
+            }
+            _ => false,
+        })
+        .map(|m| m.parent_id.clone())
+}
+
+/// Apply a change to a copy of the settings and make it current
+fn change_settings(
+    state: &mut ChatState,
+    change: impl FnOnce(&mut ConversationSettings),
+) -> Result<(), String> {
+    let mut settings = state.settings.clone();
+    change(&mut settings);
+    apply_settings(state, settings).map_err(|e| e.to_string())
+}
+
+fn model_command(state: &mut ChatState, args: &str) -> Result<String, String> {
+    if !args.is_empty() {
+        let model_config = match args.split_once('/') {
+            Some((provider, model)) => ModelConfig {
+                model: model.to_string(),
+                provider: provider.to_string(),
+            },
+            None => ModelConfig {
+                model: args.to_string(),
+                provider: state.settings.model_config.provider.clone(),
+            },
+        };
+        change_settings(state, |settings| settings.model_config = model_config)?;
+    }
+
+    let model_config = &state.settings.model_config;
+    Ok(format!(
+        "Model: {}/{}",
+        model_config.provider, model_config.model
+    ))
+}
+
+fn system_command(state: &mut ChatState, args: &str) -> Result<String, String> {
+    match args {
+        "" => {}
+        "--clear" => change_settings(state, |settings| settings.system_prompt = None)?,
+        prompt => {
+            let prompt = prompt.to_string();
+            change_settings(state, |settings| settings.system_prompt = Some(prompt))?
+        }
+    }
+
+    Ok(match &state.settings.system_prompt {
+        Some(prompt) => format!("System prompt:\n{}", prompt),
+        None => "No system prompt".to_string(),
+    })
+}
+
+fn temperature_command(state: &mut ChatState, args: &str) -> Result<String, String> {
+    match args {
+        "" => {}
+        "default" => change_settings(state, |settings| settings.temperature = None)?,
+        value => {
+            let temperature: f32 = value
+                .parse()
+                .map_err(|_| format!("Invalid temperature: {}", value))?;
+            change_settings(state, |settings| settings.temperature = Some(temperature))?
+        }
+    }
+
+    Ok(match state.settings.temperature {
+        Some(temperature) => format!("Temperature: {}", temperature),
+        None => "Temperature: provider default".to_string(),
+    })
+}
+
+fn branch_command(state: &mut ChatState, args: &str) -> Result<String, String> {
+    if args.is_empty() {
+        return Ok(match &state.head {
+            Some(head) => format!("Head: {}", head),
+            None => "The conversation is empty".to_string(),
+        });
+    }
+
+    let id = match match_prefix(state.messages.keys(), args)? {
+        Some(id) => id,
+        None => match state.get_message(args)? {
+            Some(_) => args.to_string(),
+            None => return Err(format!("No message matches {}", args)),
+        },
+    };
+
+    state.set_head(Some(id.clone()))?;
+    Ok(format!("Head: {}", id))
+}
+
+/// Unlike the `undo` request, which reverts the last head move, this steps back
+/// over the last exchange however many moves it took
+fn undo_command(state: &mut ChatState, _args: &str) -> Result<String, String> {
+    let target = undo_target(&state.get_chain()).ok_or("Nothing to undo")?;
+
+    state.set_head(target)?;
+    Ok(match &state.head {
+        Some(head) => format!("Head: {}", head),
+        None => "The conversation is empty".to_string(),
+    })
+}
+
+fn tools_command(state: &mut ChatState, _args: &str) -> Result<String, String> {
+    let tools = state.get_tools()?.unwrap_or_default();
+    if tools.is_empty() {
+        return Ok("No tools available".to_string());
+    }
+
+    Ok(tools
+        .iter()
+        .map(|tool| match &tool.description {
+            Some(description) => format!("{}: {}", tool.name, description),
+            None => tool.name.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+fn export_command(state: &mut ChatState, _args: &str) -> Result<String, String> {
+    let sections: Vec<String> = state
+        .model_chain()
+        .iter()
+        .map(|m| {
+            let (role, text) = render_entry(&m.entry);
+            format!("### {}\n\n{}", role, text)
+        })
+        .collect();
+
+    Ok(format!(
+        "# {}\n\n{}",
+        state.settings.title,
+        sections.join("\n\n")
+    ))
+}
+
+fn help_command(_state: &mut ChatState, _args: &str) -> Result<String, String> {
+    Ok(COMMANDS
+        .iter()
+        .map(|command| format!("{} - {}", command.usage, command.description))
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+impl ChatState {
+    /// Add a user message, or run it as a slash command when commands are on
+    pub fn add_user_message(&mut self, message: Message) {
+        if self.settings.slash_commands {
+            if let Some(text) = command_text(&message) {
+                self.run_command(&text);
+                return;
+            }
+        }
+
+        let message = self.take_attachments(message);
+        self.add_message(ChatEntry::Message(message));
+    }
+
+    /// Run a slash command and add its reply as a local entry
+    pub fn run_command(&mut self, text: &str) {
+        let (name, args) = split_command(text);
+        log(&format!("Running command /{} with args: {:?}", name, args));
+
+        let result = match COMMANDS.iter().find(|command| command.name == name) {
+            Some(command) => (command.handler)(self, args),
+            None => Err(format!("Unknown command /{}. Try /help.", name)),
+        };
+        let (output, is_error) = match result {
+            Ok(output) => (output, false),
+            Err(e) => {
+                log(&format!("Command /{} failed: {}", name, e));
+                (e, true)
+            }
+        };
+
+        self.add_message(ChatEntry::Local(LocalEntry {
+            command: text.to_string(),
+            output,
+            is_error,
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{command_text, match_prefix, split_command, undo_target};
+    use crate::state::{ChatEntry, ChatMessage, LocalEntry};
+    use genai_types::messages::Role;
+    use genai_types::{Message, MessageContent};
+
+    fn text(role: Role, text: &str) -> Message {
+        Message {
+            role,
+            content: vec![MessageContent::Text {
+                text: text.to_string(),
+            }],
+        }
+    }
+
+    fn chat_message(id: &str, parent_id: Option<&str>, entry: ChatEntry) -> ChatMessage {
+        ChatMessage {
+            id: Some(id.to_string()),
+            parent_id: parent_id.map(str::to_string),
+            entry,
+            metadata: None,
+        }
+    }
+
+    #[test]
+    fn recognizes_commands_in_user_text() {
+        assert_eq!(
+            command_text(&text(Role::User, "  /model claude  ")),
+            Some("/model claude".to_string())
+        );
+        assert_eq!(command_text(&text(Role::User, "hello /model")), None);
+        assert_eq!(command_text(&text(Role::Assistant, "/help")), None);
+    }
+
+    #[test]
+    fn double_slash_is_not_a_command() {
+        assert_eq!(
+            command_text(&text(Role::User, "//etc/hosts is a path")),
+            None
+        );
+    }
+
+    #[test]
+    fn messages_with_non_text_content_are_not_commands() {
+        let mut message = text(Role::User, "/help");
+        message.content.push(MessageContent::ToolResult {
+            tool_use_id: "tool".to_string(),
+            content: vec![],
+            is_error: None,
+        });
+        assert_eq!(command_text(&message), None);
+    }
+
+    #[test]
+    fn splits_name_and_trimmed_args() {
+        assert_eq!(split_command("/help"), ("help", ""));
+        assert_eq!(
+            split_command("/system  be brief \n"),
+            ("system", "be brief")
+        );
+        assert_eq!(
+            split_command("/model\tanthropic/claude"),
+            ("model", "anthropic/claude")
+        );
+    }
+
+    #[test]
+    fn branch_prefix_must_be_unique() {
+        let ids = ["abc123".to_string(), "abd456".to_string()];
+        assert_eq!(
+            match_prefix(ids.iter(), "abc"),
+            Ok(Some("abc123".to_string()))
+        );
+        assert_eq!(match_prefix(ids.iter(), "x"), Ok(None));
+        assert_eq!(
+            match_prefix(ids.iter(), "ab"),
+            Err("ab matches 2 messages".to_string())
+        );
+    }
+
+    #[test]
+    fn undoes_to_before_the_last_typed_user_message() {
+        let tool_result = Message {
+            role: Role::User,
+            content: vec![MessageContent::ToolResult {
+                tool_use_id: "tool".to_string(),
+                content: vec![],
+                is_error: None,
+            }],
+        };
+        let chain = vec![
+            chat_message("1", None, ChatEntry::Message(text(Role::User, "first"))),
+            chat_message(
+                "2",
+                Some("1"),
+                ChatEntry::Message(text(Role::Assistant, "reply")),
+            ),
+            chat_message(
+                "3",
+                Some("2"),
+                ChatEntry::Message(text(Role::User, "second")),
+            ),
+            chat_message("4", Some("3"), ChatEntry::Message(tool_result)),
+            chat_message(
+                "5",
+                Some("4"),
+                ChatEntry::Local(LocalEntry {
+                    command: "/help".to_string(),
+                    output: "...".to_string(),
+                    is_error: false,
+                }),
+            ),
+        ];
+        assert_eq!(undo_target(&chain), Some(Some("2".to_string())));
+        assert_eq!(undo_target(&chain[..2]), Some(None));
+        assert_eq!(undo_target(&chain[1..2]), None);
+    }
+}
@@ -0,0 +1,191 @@
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::timing;
+use crate::state::{ChatState, MessageMetadata, ModelConfig};
+use genai_types::CompletionResponse;
+use std::fmt::Display;
+
+/// HTTP statuses that mark a provider error as transient
+const RETRYABLE_STATUSES: [u16; 5] = [429, 500, 502, 503, 529];
+
+/// Error patterns treated as transient when no list is configured. Status
+/// codes only count next to "status" or "HTTP", so numbers elsewhere in an
+/// error, such as token counts, don't match.
+pub fn default_retryable_errors() -> Vec<String> {
+    let mut patterns: Vec<String> = [
+        "overloaded",
+        "rate limit",
+        "rate_limit",
+        "too many requests",
+        "internal server error",
+        "bad gateway",
+        "service unavailable",
+        "timeout",
+        "timed out",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect();
+    for status in RETRYABLE_STATUSES {
+        patterns.push(format!("status {}", status));
+        patterns.push(format!("status: {}", status));
+        patterns.push(format!("status code {}", status));
+        patterns.push(format!("http {}", status));
+    }
+    patterns
+}
+
+/// Whether `pattern` occurs in `text` without a letter or digit directly
+/// before or after it. Both are expected to be lowercase.
+fn contains_word(text: &str, pattern: &str) -> bool {
+    if pattern.is_empty() {
+        return false;
+    }
+    text.match_indices(pattern).any(|(start, matched)| {
+        let before = text[..start].chars().next_back();
+        let after = text[start + matched.len()..].chars().next();
+        !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+    })
+}
+
+/// Why a completion attempt failed
+#[derive(Debug, Clone)]
+pub enum CompletionFailure {
+    /// The proxy actor could not be reached
+    Transport(String),
+
+    /// The provider answered with an error
+    Provider(String),
+
+    /// Anything else, such as an unknown provider
+    Other(String),
+}
+
+impl Display for CompletionFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompletionFailure::Transport(e) => write!(f, "Failed to send request to proxy: {}", e),
+            CompletionFailure::Provider(e) => write!(f, "Error from proxy: {}", e),
+            CompletionFailure::Other(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl ChatState {
+    /// Whether a failed attempt is worth retrying or passing to a fallback model
+    fn is_retryable(&self, failure: &CompletionFailure) -> bool {
+        match failure {
+            CompletionFailure::Transport(_) => true,
+            CompletionFailure::Provider(error) => {
+                let error = error.to_lowercase();
+                self.settings
+                    .retryable_errors
+                    .iter()
+                    .any(|pattern| contains_word(&error, &pattern.to_lowercase()))
+            }
+            CompletionFailure::Other(_) => false,
+        }
+    }
+
+    /// Generate a completion with `primary`, retrying transient failures with
+    /// backoff and then falling through to `settings.fallback_models` in order.
+    /// The returned metadata records which model answered.
+    pub fn complete_with_failover(
+        &mut self,
+        primary: ModelConfig,
+    ) -> Result<(CompletionResponse, MessageMetadata), String> {
+        let retry = self.settings.completion_retry.clone();
+        let mut candidates = vec![primary];
+        candidates.extend(self.settings.fallback_models.clone());
+
+        let mut errors = Vec::new();
+        for model_config in candidates {
+            let mut retries = 0;
+            loop {
+                match self.generate_proxy_completion(&model_config) {
+                    Ok(completion) => {
+                        let metadata = MessageMetadata {
+                            answered_by: Some(model_config),
+                            failover_errors: errors,
+                            settings_version: self.settings_version.clone(),
+                            ..Default::default()
+                        };
+                        return Ok((completion, metadata));
+                    }
+                    Err(failure) => {
+                        let retryable = self.is_retryable(&failure);
+                        log(&format!(
+                            "Completion with {}/{} failed (retryable: {}): {}",
+                            model_config.provider, model_config.model, retryable, failure
+                        ));
+                        errors.push(format!(
+                            "{}/{}: {}",
+                            model_config.provider, model_config.model, failure
+                        ));
+
+                        if !retryable {
+                            return Err(failure.to_string());
+                        }
+                        if retries >= retry.max_retries {
+                            break;
+                        }
+
+                        retries += 1;
+                        let backoff = retry.backoff_for(retries);
+                        log(&format!(
+                            "Retrying in {} ms (retry {}/{})",
+                            backoff, retries, retry.max_retries
+                        ));
+                        if let Err(e) = timing::sleep(backoff) {
+                            log(&format!("Failed to sleep before retry: {}", e));
+                        }
+                    }
+                }
+            }
+        }
+
+        Err(format!(
+            "All models failed to generate a completion: {}",
+            errors.join("; ")
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{contains_word, default_retryable_errors};
+
+    fn is_retryable(error: &str) -> bool {
+        let error = error.to_lowercase();
+        default_retryable_errors()
+            .iter()
+            .any(|pattern| contains_word(&error, pattern))
+    }
+
+    #[test]
+    fn matches_whole_words_only() {
+        assert!(contains_word("error: overloaded_error", "overloaded"));
+        assert!(contains_word("status 500", "status 500"));
+        assert!(!contains_word("status 5000", "status 500"));
+        assert!(!contains_word("timeouts", "timeout"));
+        assert!(!contains_word("anything", ""));
+    }
+
+    #[test]
+    fn retries_transient_provider_errors() {
+        assert!(is_retryable("Overloaded"));
+        assert!(is_retryable("rate_limit_error: slow down"));
+        assert!(is_retryable("HTTP 503 Service Unavailable"));
+        assert!(is_retryable("request failed with status 529"));
+    }
+
+    #[test]
+    fn ignores_numbers_outside_status_codes() {
+        assert!(!is_retryable(
+            "prompt is too long: 250000 tokens > 200000 maximum"
+        ));
+        assert!(!is_retryable("max_tokens: 500 exceeds the model limit"));
+        assert!(!is_retryable(
+            "invalid_request_error: messages.429.content is empty"
+        ));
+    }
+}
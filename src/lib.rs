@@ -1,23 +1,44 @@
+mod audit;
 mod bindings;
+mod cache;
+mod catalog;
+mod commands;
+mod completion;
+mod moderation;
+mod params;
+mod patch;
+mod presets;
 mod protocol;
 mod proxy;
+mod resources;
+mod routing;
+mod schema;
+mod settings_history;
 mod state;
+mod template;
+mod title;
+mod tools;
+mod undo;
+mod validation;
 
 use crate::bindings::exports::theater::simple::actor::Guest;
 use crate::bindings::exports::theater::simple::message_server_client::Guest as MessageServerClient;
 use crate::bindings::exports::theater::simple::supervisor_handlers::Guest as SupervisorHandlers;
 use crate::bindings::theater::simple::runtime::log;
 use crate::bindings::theater::simple::store::new;
-use crate::protocol::{create_error_response, ChatStateRequest, ChatStateResponse};
+use crate::protocol::{
+    create_error_response, ChatStateRequest, ChatStateResponse, McpNotification, TOOLS_LIST_CHANGED,
+};
 use crate::proxy::Proxy;
 use crate::state::ChatState;
+use crate::validation::SettingsError;
 
 use bindings::theater::simple::random::generate_uuid;
 use bindings::theater::simple::store::{self};
 use bindings::theater::simple::types::{WitActorError, WitErrorType};
 use serde::{Deserialize, Serialize};
-use serde_json::{from_slice, to_vec};
-use state::{ChatEntry, ConversationSettings, InitConversationSettings};
+use serde_json::{from_slice, to_vec, Value};
+use state::{ConversationSettings, InitConversationSettings};
 use std::collections::HashMap;
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -25,6 +46,14 @@ struct InitData {
     store_id: Option<String>,
     conversation_id: Option<String>,
     config: Option<InitConversationSettings>,
+
+    /// Start from a stored settings preset when no config is given
+    #[serde(default)]
+    preset: Option<String>,
+
+    /// JSON Merge Patch applied on top of the preset
+    #[serde(default)]
+    overrides: Option<Value>,
 }
 
 const ANTHROPIC_PROXY_MANIFEST: &str =
@@ -54,15 +83,6 @@ impl Guest for Component {
                     parsed_init_state.conversation_id
                 ));
 
-                let mut proxies = HashMap::new();
-                let anthropic_proxy = Proxy::new("anthropic", ANTHROPIC_PROXY_MANIFEST)
-                    .map_err(|e| format!("Failed to spawn anthropic-proxy: {}", e))?;
-
-                let google_proxy = Proxy::new("google", GOOGLE_PROXY_MANIFEST)
-                    .map_err(|e| format!("Failed to spawn google-proxy: {}", e))?;
-                proxies.insert("anthropic".to_string(), anthropic_proxy);
-                proxies.insert("google".to_string(), google_proxy);
-
                 let store_id = match parsed_init_state.store_id {
                     Some(store_id) => store_id,
                     None => {
@@ -79,8 +99,27 @@ impl Guest for Component {
                     }
                 };
 
-                let conversation_settings = match parsed_init_state.config {
-                    Some(config) => config.into(),
+                // An explicit config wins over a preset
+                let init_settings: Option<ConversationSettings> =
+                    match (parsed_init_state.config, parsed_init_state.preset) {
+                        (Some(config), _) => Some(config.into()),
+                        (None, Some(preset)) => Some(presets::load_preset(
+                            &store_id,
+                            &preset,
+                            parsed_init_state.overrides.as_ref(),
+                        )?),
+                        (None, None) => None,
+                    };
+
+                let conversation_settings = match init_settings {
+                    Some(settings) => {
+                        if let Err(errors) = validation::validate_settings(&settings) {
+                            let message = validation::describe_errors(&errors);
+                            log(&message);
+                            return Err(message);
+                        }
+                        settings
+                    }
                     None => {
                         // check if we have conversation settings stored
                         log("No config provided, checking store for existing settings");
@@ -121,7 +160,14 @@ impl Guest for Component {
                     }
                 };
 
-                let chat_state = ChatState::new(
+                // Proxies are spawned on first use
+                let proxies: HashMap<String, Proxy> = conversation_settings
+                    .providers
+                    .iter()
+                    .map(|provider| (provider.name.clone(), Proxy::new(provider)))
+                    .collect();
+
+                let mut chat_state = ChatState::new(
                     param,
                     conversation_id,
                     proxies,
@@ -183,7 +229,7 @@ impl MessageServerClient for Component {
                 }
                 ChatStateRequest::AddMessage { message } => {
                     log(&format!("Adding message: {:?}", message));
-                    chat_state.add_message(ChatEntry::Message(message));
+                    chat_state.add_user_message(message);
                     let updated_state_bytes = to_vec(&chat_state)
                         .map_err(|e| format!("Failed to serialize updated state: {}", e))?;
                     Ok((Some(updated_state_bytes),))
@@ -218,6 +264,20 @@ impl MessageServerClient for Component {
                 }
             },
             Err(_) => {
+                // MCP actors notify us when their tool lists change. The sender
+                // is not known here, so every running server is re-queried.
+                if let Ok(notification) = from_slice::<McpNotification>(&_data) {
+                    if notification.method == TOOLS_LIST_CHANGED {
+                        log("Received tools list changed notification");
+                        if let Err(e) = chat_state.refresh_tools() {
+                            log(&format!("Failed to refresh tools: {}", e));
+                        }
+                        let updated_state_bytes = to_vec(&chat_state)
+                            .map_err(|e| format!("Failed to serialize updated state: {}", e))?;
+                        return Ok((Some(updated_state_bytes),));
+                    }
+                }
+
                 log(&format!(
                     "Received unrecognized message: {}",
                     String::from_utf8_lossy(&_data)
@@ -264,6 +324,8 @@ impl MessageServerClient for Component {
         let request: ChatStateRequest =
             from_slice(&data).map_err(|e| format!("Failed to parse request: {}", e))?;
 
+        chat_state.stop_idle_proxies();
+
         // Process request based on action
         let response = match request {
             ChatStateRequest::ContinueProcessing => {
@@ -277,7 +339,7 @@ impl MessageServerClient for Component {
                 }
             }
             ChatStateRequest::AddMessage { message } => {
-                chat_state.add_message(ChatEntry::Message(message));
+                chat_state.add_user_message(message);
                 ChatStateResponse::Success
             }
             ChatStateRequest::GenerateCompletion => match chat_state.pending_completion {
@@ -331,10 +393,24 @@ impl MessageServerClient for Component {
                     }
                 }
             }
+            ChatStateRequest::Undo => match chat_state.undo_head() {
+                Ok(head) => ChatStateResponse::Head { head },
+                Err(e) => {
+                    log(&format!("Failed to undo: {}", e));
+                    create_error_response("undo_error", &e)
+                }
+            },
+            ChatStateRequest::Redo => match chat_state.redo_head() {
+                Ok(head) => ChatStateResponse::Head { head },
+                Err(e) => {
+                    log(&format!("Failed to redo: {}", e));
+                    create_error_response("undo_error", &e)
+                }
+            },
             ChatStateRequest::GetMessage { message_id } => {
                 match chat_state.get_message(&message_id) {
                     Ok(Some(message)) => ChatStateResponse::ChatMessage {
-                        message: message.clone(),
+                        message: Box::new(message.clone()),
                     },
                     Ok(None) => ChatStateResponse::Error {
                         error: protocol::ErrorInfo {
@@ -356,14 +432,117 @@ impl MessageServerClient for Component {
                 let client_settings = protocol::internal_to_client_settings(settings);
 
                 ChatStateResponse::Settings {
-                    settings: client_settings,
+                    settings: Box::new(client_settings),
                 }
             }
             ChatStateRequest::UpdateSettings { settings } => {
                 log("Updating settings");
                 log(&format!("Settings: {:?}", settings));
-                chat_state.update_settings(settings.clone());
-                ChatStateResponse::Success
+                match validation::apply_settings(&mut chat_state, *settings) {
+                    Ok(()) => ChatStateResponse::Success,
+                    Err(e) => e.into_response(),
+                }
+            }
+            ChatStateRequest::PatchSettings { patch } => {
+                log("Patching settings");
+                let result = chat_state
+                    .patched_settings(&patch)
+                    .map_err(SettingsError::Patch)
+                    .and_then(|settings| validation::apply_settings(&mut chat_state, settings));
+                match result {
+                    Ok(()) => ChatStateResponse::Settings {
+                        settings: Box::new(protocol::internal_to_client_settings(
+                            &chat_state.settings,
+                        )),
+                    },
+                    Err(e) => e.into_response(),
+                }
+            }
+            ChatStateRequest::SavePreset { name, settings } => {
+                let settings = match settings {
+                    Some(settings) => *settings,
+                    None => chat_state.settings.clone(),
+                };
+                let result = validation::validate_settings(&settings)
+                    .map_err(SettingsError::Invalid)
+                    .and_then(|()| {
+                        chat_state
+                            .save_preset(&name, &settings)
+                            .map_err(SettingsError::Preset)
+                    });
+                match result {
+                    Ok(()) => ChatStateResponse::Success,
+                    Err(e) => e.into_response(),
+                }
+            }
+            ChatStateRequest::ListPresets => match chat_state.list_presets() {
+                Ok(names) => ChatStateResponse::PresetsList { names },
+                Err(e) => {
+                    log(&format!("Failed to list presets: {}", e));
+                    create_error_response("preset_error", &e)
+                }
+            },
+            ChatStateRequest::DeletePreset { name } => match chat_state.delete_preset(&name) {
+                Ok(()) => ChatStateResponse::Success,
+                Err(e) => {
+                    log(&format!("Failed to delete preset: {}", e));
+                    create_error_response("preset_error", &e)
+                }
+            },
+            ChatStateRequest::ApplyPreset { name, overrides } => {
+                log(&format!("Applying settings preset {}", name));
+                let result = chat_state
+                    .preset_settings(&name, overrides.as_ref())
+                    .map_err(SettingsError::Preset)
+                    .and_then(|settings| validation::apply_settings(&mut chat_state, settings));
+                match result {
+                    Ok(()) => ChatStateResponse::Settings {
+                        settings: Box::new(protocol::internal_to_client_settings(
+                            &chat_state.settings,
+                        )),
+                    },
+                    Err(e) => e.into_response(),
+                }
+            }
+            ChatStateRequest::PreviewSystemPrompt { template } => {
+                // Preview for the model the next completion would be routed to
+                let model = chat_state.route_model();
+                match chat_state.render_system_prompt(template.as_deref(), &model) {
+                    Ok(text) => ChatStateResponse::SystemPrompt { text },
+                    Err(e) => {
+                        log(&format!("Failed to render system prompt: {}", e));
+                        create_error_response("system_prompt_error", &e)
+                    }
+                }
+            }
+            ChatStateRequest::RegenerateTitle => match chat_state.regenerate_title() {
+                Ok(title) => ChatStateResponse::TitleChanged { title },
+                Err(e) => {
+                    log(&format!("Failed to regenerate title: {}", e));
+                    create_error_response("title_error", &e)
+                }
+            },
+            ChatStateRequest::GetSettingsHistory => match chat_state.get_settings_history() {
+                Ok(versions) => ChatStateResponse::SettingsHistory { versions },
+                Err(e) => {
+                    log(&format!("Failed to read settings history: {}", e));
+                    create_error_response("settings_history_error", &e)
+                }
+            },
+            ChatStateRequest::RollbackSettings { version } => {
+                log(&format!("Rolling settings back to version {}", version));
+                let result = chat_state
+                    .settings_at_version(&version)
+                    .map_err(SettingsError::Rollback)
+                    .and_then(|settings| validation::apply_settings(&mut chat_state, settings));
+                match result {
+                    Ok(()) => ChatStateResponse::Settings {
+                        settings: Box::new(protocol::internal_to_client_settings(
+                            &chat_state.settings,
+                        )),
+                    },
+                    Err(e) => e.into_response(),
+                }
             }
             ChatStateRequest::GetHistory => ChatStateResponse::History {
                 messages: chat_state.get_chain(),
@@ -371,7 +550,7 @@ impl MessageServerClient for Component {
             ChatStateRequest::ListModels => {
                 let models = chat_state.list_models();
                 match models {
-                    Ok(models) => ChatStateResponse::ModelsList { models },
+                    Ok((models, errors)) => ChatStateResponse::ModelsList { models, errors },
                     Err(e) => {
                         log(&format!("Failed to list models: {}", e));
                         create_error_response("models_error", &e)
@@ -385,6 +564,122 @@ impl MessageServerClient for Component {
                     create_error_response("tools_error", &e)
                 }
             },
+            ChatStateRequest::RefreshTools => match chat_state.refresh_tools() {
+                Ok(tools) => ChatStateResponse::ToolsList { tools },
+                Err(e) => {
+                    log(&format!("Failed to refresh tools: {}", e));
+                    create_error_response("tools_error", &e)
+                }
+            },
+            ChatStateRequest::HideMessage {
+                message_id,
+                subtree,
+            } => match chat_state.hide_message(&message_id, subtree) {
+                Ok(_) => ChatStateResponse::Success,
+                Err(e) => {
+                    log(&format!("Failed to hide message: {}", e));
+                    create_error_response("moderation_error", &e)
+                }
+            },
+            ChatStateRequest::UnhideMessage { message_id } => {
+                match chat_state.unhide_message(&message_id) {
+                    Ok(_) => ChatStateResponse::Success,
+                    Err(e) => {
+                        log(&format!("Failed to unhide message: {}", e));
+                        create_error_response("moderation_error", &e)
+                    }
+                }
+            }
+            ChatStateRequest::RedactMessage { message_id } => {
+                match chat_state.redact_message(&message_id) {
+                    Ok(replacement_id) => ChatStateResponse::MessageRedacted {
+                        message_id,
+                        replacement_id,
+                    },
+                    Err(e) => {
+                        log(&format!("Failed to redact message: {}", e));
+                        create_error_response("moderation_error", &e)
+                    }
+                }
+            }
+            ChatStateRequest::GetMessageAudit { since } => {
+                match chat_state.get_message_audit(since) {
+                    Ok(entries) => ChatStateResponse::MessageAudit { entries },
+                    Err(e) => {
+                        log(&format!("Failed to read message audit log: {}", e));
+                        create_error_response("audit_error", &e)
+                    }
+                }
+            }
+            ChatStateRequest::ClearToolCache => {
+                chat_state.clear_tool_cache();
+                ChatStateResponse::Success
+            }
+            ChatStateRequest::GetToolAudit { since, tool } => {
+                match chat_state.get_tool_audit(since, tool) {
+                    Ok(entries) => ChatStateResponse::ToolAudit { entries },
+                    Err(e) => {
+                        log(&format!("Failed to read tool audit log: {}", e));
+                        create_error_response("audit_error", &e)
+                    }
+                }
+            }
+            ChatStateRequest::AddProvider { provider } => {
+                match chat_state.add_provider(provider) {
+                    Ok(_) => ChatStateResponse::Success,
+                    Err(e) => e.into_response(),
+                }
+            }
+            ChatStateRequest::RemoveProvider { name } => match chat_state.remove_provider(&name) {
+                Ok(_) => ChatStateResponse::Success,
+                Err(e) => e.into_response(),
+            },
+            ChatStateRequest::GetProviderStatus => ChatStateResponse::ProviderStatus {
+                providers: chat_state.get_provider_status(),
+            },
+            ChatStateRequest::ListResources => match chat_state.list_resources() {
+                Ok(resources) => ChatStateResponse::ResourcesList { resources },
+                Err(e) => {
+                    log(&format!("Failed to list resources: {}", e));
+                    create_error_response("resources_error", &e)
+                }
+            },
+            ChatStateRequest::ReadResource { server, uri } => {
+                match chat_state.read_resource(&server, &uri) {
+                    Ok(contents) => ChatStateResponse::ResourceContents { contents },
+                    Err(e) => {
+                        log(&format!("Failed to read resource: {}", e));
+                        create_error_response("resources_error", &e)
+                    }
+                }
+            }
+            ChatStateRequest::AttachResource { server, uri } => {
+                match chat_state.attach_resource(&server, &uri) {
+                    Ok(_) => ChatStateResponse::Success,
+                    Err(e) => {
+                        log(&format!("Failed to attach resource: {}", e));
+                        create_error_response("resources_error", &e)
+                    }
+                }
+            }
+            ChatStateRequest::ListPrompts => match chat_state.list_prompts() {
+                Ok(prompts) => ChatStateResponse::PromptsList { prompts },
+                Err(e) => {
+                    log(&format!("Failed to list prompts: {}", e));
+                    create_error_response("prompts_error", &e)
+                }
+            },
+            ChatStateRequest::GetPrompt {
+                server,
+                name,
+                arguments,
+            } => match chat_state.get_prompt(&server, &name, arguments) {
+                Ok(messages) => ChatStateResponse::PromptMessages { messages },
+                Err(e) => {
+                    log(&format!("Failed to get prompt: {}", e));
+                    create_error_response("prompts_error", &e)
+                }
+            },
             ChatStateRequest::GetMetadata => ChatStateResponse::Metadata {
                 conversation_id: chat_state.conversation_id.clone(),
                 store_id: chat_state.store_id.clone(),
@@ -491,7 +786,7 @@ impl MessageServerClient for Component {
 
 impl SupervisorHandlers for Component {
     fn handle_child_error(
-        _state: Option<Vec<u8>>,
+        state: Option<Vec<u8>>,
         params: (String, WitActorError),
     ) -> Result<(Option<Vec<u8>>,), String> {
         log("Handling child error in chat-state");
@@ -503,6 +798,19 @@ impl SupervisorHandlers for Component {
             child, error
         ));
 
+        // A crashed MCP server or proxy is restarted instead of failing chat-state
+        if let Some(s) = &state {
+            let mut chat_state: ChatState =
+                from_slice(s).map_err(|e| format!("Failed to deserialize state: {}", e))?;
+            if chat_state.handle_mcp_server_failure(&child)
+                || chat_state.handle_proxy_failure(&child)
+            {
+                let updated_state_bytes = to_vec(&chat_state)
+                    .map_err(|e| format!("Failed to serialize updated state: {}", e))?;
+                return Ok((Some(updated_state_bytes),));
+            }
+        }
+
         match error {
             WitActorError {
                 error_type: WitErrorType::Internal,
@@ -535,10 +843,24 @@ impl SupervisorHandlers for Component {
 
     fn handle_child_exit(
         state: Option<Vec<u8>>,
-        _params: (String, Option<Vec<u8>>),
+        params: (String, Option<Vec<u8>>),
     ) -> Result<(Option<Vec<u8>>,), String> {
         log("Handling child exit in chat-state");
-        Ok((state,))
+        let (child, _) = params;
+
+        let mut chat_state: ChatState = match state {
+            Some(s) => from_slice(&s).map_err(|e| format!("Failed to deserialize state: {}", e))?,
+            None => return Ok((state,)),
+        };
+
+        // MCP servers and proxies are expected to run until we stop them
+        if !chat_state.handle_mcp_server_failure(&child) {
+            chat_state.handle_proxy_failure(&child);
+        }
+
+        let updated_state_bytes =
+            to_vec(&chat_state).map_err(|e| format!("Failed to serialize updated state: {}", e))?;
+        Ok((Some(updated_state_bytes),))
     }
 
     fn handle_child_external_stop(
@@ -0,0 +1,677 @@
+use crate::audit::{append_log, read_log, LogEntry};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store;
+use crate::bindings::theater::simple::timing;
+use crate::cache::cache_key;
+use crate::protocol::ChatStateResponse;
+use crate::state::{ChatEntry, ChatError, ChatMessage, ChatState};
+use genai_types::{Message, MessageContent};
+use mcp_protocol::tool::ToolContent;
+use serde::{Deserialize, Serialize};
+use serde_json::{to_vec, Value};
+use std::collections::{HashMap, HashSet};
+
+/// Text that replaces redacted content
+pub const REDACTION_MARKER: &str = "[redacted]";
+
+/// Messages hidden or redacted in a conversation
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Moderation {
+    /// Hidden message ids, mapped to whether everything after them is hidden too
+    #[serde(default)]
+    pub hidden: HashMap<String, bool>,
+
+    /// Original ids of redacted messages, which are no longer served
+    #[serde(default)]
+    pub redacted: HashSet<String>,
+}
+
+/// One hide, unhide or redaction, as recorded in the message audit log
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MessageAuditEntry {
+    pub timestamp: u64,
+
+    /// `hide`, `unhide` or `redact`
+    pub action: String,
+    pub message_id: String,
+
+    /// Whether a hide covered the message's descendants
+    #[serde(default)]
+    pub subtree: bool,
+
+    /// Id of the redacted copy that took the message's place
+    pub replacement_id: Option<String>,
+
+    /// Descendants re-stored under the redacted copy
+    #[serde(default)]
+    pub relinked: usize,
+
+    /// Whether the original blob was deleted. The store has no delete, so a
+    /// redacted original stays in the store but is no longer served.
+    #[serde(default)]
+    pub original_removed: bool,
+
+    pub previous: Option<String>,
+}
+
+impl LogEntry for MessageAuditEntry {
+    fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    fn previous(&self) -> Option<String> {
+        self.previous.clone()
+    }
+
+    fn set_previous(&mut self, previous: Option<String>) {
+        self.previous = previous;
+    }
+}
+
+fn moderation_label(conversation_id: &str) -> String {
+    format!("moderation_{}", conversation_id)
+}
+
+/// Hidden and redacted messages stored for a conversation, or none
+pub fn load_moderation(store_id: &str, conversation_id: &str) -> Moderation {
+    let moderation_ref = match store::get_by_label(store_id, &moderation_label(conversation_id)) {
+        Ok(Some(moderation_ref)) => moderation_ref,
+        Ok(None) => return Moderation::default(),
+        Err(e) => {
+            log(&format!("Failed to check for hidden messages: {}", e));
+            return Moderation::default();
+        }
+    };
+
+    store::get(store_id, &moderation_ref)
+        .map_err(|e| format!("Failed to read hidden messages: {}", e))
+        .and_then(|bytes| {
+            serde_json::from_slice(&bytes)
+                .map_err(|e| format!("Failed to parse hidden messages: {}", e))
+        })
+        .unwrap_or_else(|e| {
+            log(&e);
+            Moderation::default()
+        })
+}
+
+fn redact_content(content: Vec<MessageContent>) -> Vec<MessageContent> {
+    // Tool ids are kept so tool calls and their results still pair up
+    content
+        .into_iter()
+        .map(|content| match content {
+            MessageContent::Text { .. } => MessageContent::Text {
+                text: REDACTION_MARKER.to_string(),
+            },
+            MessageContent::ToolUse { id, name, .. } => MessageContent::ToolUse {
+                id,
+                name,
+                input: Value::Object(Default::default()),
+            },
+            MessageContent::ToolResult {
+                tool_use_id,
+                is_error,
+                ..
+            } => MessageContent::ToolResult {
+                tool_use_id,
+                content: vec![ToolContent::Text {
+                    text: REDACTION_MARKER.to_string(),
+                }],
+                is_error,
+            },
+        })
+        .collect()
+}
+
+fn redact_entry(entry: ChatEntry) -> ChatEntry {
+    match entry {
+        ChatEntry::Message(message) => ChatEntry::Message(Message {
+            role: message.role,
+            content: redact_content(message.content),
+        }),
+        ChatEntry::Completion(mut completion) => {
+            completion.content = redact_content(completion.content);
+            ChatEntry::Completion(completion)
+        }
+        ChatEntry::Error(error) => ChatEntry::Error(ChatError {
+            message: REDACTION_MARKER.to_string(),
+            code: error.code,
+        }),
+        ChatEntry::Local(mut local) => {
+            local.command = REDACTION_MARKER.to_string();
+            local.output = REDACTION_MARKER.to_string();
+            ChatEntry::Local(local)
+        }
+    }
+}
+
+fn entry_content(entry: &ChatEntry) -> &[MessageContent] {
+    match entry {
+        ChatEntry::Message(message) => &message.content,
+        ChatEntry::Completion(completion) => &completion.content,
+        ChatEntry::Error(_) | ChatEntry::Local(_) => &[],
+    }
+}
+
+/// Id, tool name and arguments of each tool call in an entry
+fn tool_uses(entry: &ChatEntry) -> Vec<(String, String, Value)> {
+    entry_content(entry)
+        .iter()
+        .filter_map(|content| match content {
+            MessageContent::ToolUse { id, name, input } => {
+                Some((id.clone(), name.clone(), input.clone()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+fn has_tool_use(message: &ChatMessage) -> bool {
+    match &message.entry {
+        ChatEntry::Completion(completion) => completion
+            .content
+            .iter()
+            .any(|c| matches!(c, MessageContent::ToolUse { .. })),
+        _ => false,
+    }
+}
+
+fn has_tool_results(message: &ChatMessage) -> bool {
+    match &message.entry {
+        ChatEntry::Message(message) => message
+            .content
+            .iter()
+            .any(|c| matches!(c, MessageContent::ToolResult { .. })),
+        _ => false,
+    }
+}
+
+impl Moderation {
+    /// Drop hidden messages from a chain, oldest first. A hidden subtree ends
+    /// the chain. A tool call and its results are hidden together so the
+    /// model never sees one without the other.
+    pub fn visible_chain(&self, chain: Vec<ChatMessage>) -> Vec<ChatMessage> {
+        if self.hidden.is_empty() {
+            return chain;
+        }
+
+        let mut hidden: Vec<bool> = Vec::with_capacity(chain.len());
+        let mut end = chain.len();
+        for (i, message) in chain.iter().enumerate() {
+            match message.id.as_ref().and_then(|id| self.hidden.get(id)) {
+                Some(true) => {
+                    end = i;
+                    break;
+                }
+                Some(false) => hidden.push(true),
+                None => hidden.push(false),
+            }
+        }
+
+        for i in 1..end {
+            if has_tool_use(&chain[i - 1])
+                && has_tool_results(&chain[i])
+                && (hidden[i - 1] || hidden[i])
+            {
+                hidden[i - 1] = true;
+                hidden[i] = true;
+            }
+        }
+
+        chain
+            .into_iter()
+            .take(end)
+            .zip(hidden)
+            .filter(|(_, hidden)| !hidden)
+            .map(|(message, _)| message)
+            .collect()
+    }
+}
+
+impl ChatState {
+    fn message_audit_label(&self) -> String {
+        format!("message_audit_{}", self.conversation_id)
+    }
+
+    fn store_moderation(&self) -> Result<(), String> {
+        let bytes = to_vec(&self.moderation)
+            .map_err(|e| format!("Failed to serialize hidden messages: {}", e))?;
+        store::store_at_label(
+            &self.store_id,
+            &moderation_label(&self.conversation_id),
+            &bytes,
+        )
+        .map_err(|e| format!("Failed to store hidden messages: {}", e))?;
+        Ok(())
+    }
+
+    fn audit_message_action(&self, entry: MessageAuditEntry) {
+        if let Err(e) = append_log(&self.store_id, &self.message_audit_label(), vec![entry]) {
+            log(&format!("Failed to record message audit entry: {}", e));
+        }
+    }
+
+    pub fn get_message_audit(&self, since: Option<u64>) -> Result<Vec<MessageAuditEntry>, String> {
+        log("Reading message audit log");
+        read_log(&self.store_id, &self.message_audit_label(), since)
+    }
+
+    /// Whether a message is shown under the same rules as `visible_chain`:
+    /// it is not hidden, not below a hidden subtree and not half of a hidden
+    /// tool call
+    pub fn is_visible(&self, id: &str) -> bool {
+        if self.moderation.hidden.is_empty() {
+            return true;
+        }
+
+        let mut chain = Vec::new();
+        let mut current_id = Some(id.to_string());
+        while let Some(id) = current_id {
+            let mut message = match self.messages.get(&id) {
+                Some(message) => message.clone(),
+                None => break,
+            };
+            current_id = message.parent_id.clone();
+            message.id = Some(id);
+            chain.push(message);
+        }
+        chain.reverse();
+
+        // The results of a tool call come after it, so look for hidden ones
+        // among its children
+        let results_hidden = chain.last().is_some_and(has_tool_use)
+            && self.messages.iter().any(|(child_id, child)| {
+                child.parent_id.as_deref() == Some(id)
+                    && has_tool_results(child)
+                    && self.moderation.hidden.contains_key(child_id)
+            });
+
+        !results_hidden
+            && self
+                .visible_chain(chain)
+                .last()
+                .and_then(|m| m.id.as_deref())
+                == Some(id)
+    }
+
+    /// Drop hidden messages from a chain, oldest first. See `Moderation::visible_chain`.
+    pub fn visible_chain(&self, chain: Vec<ChatMessage>) -> Vec<ChatMessage> {
+        self.moderation.visible_chain(chain)
+    }
+
+    /// Ids from `id` back to the root, loading each message
+    fn load_ancestors(&mut self, id: Option<String>) -> Result<Vec<String>, String> {
+        let mut ancestors = Vec::new();
+        let mut current_id = id;
+        while let Some(id) = current_id {
+            current_id = match self.get_message(&id)? {
+                Some(message) => message.parent_id,
+                None => None,
+            };
+            ancestors.push(id);
+        }
+        Ok(ancestors)
+    }
+
+    /// Hide a message, and with `subtree` everything after it, from the
+    /// history and from model requests. Hiding a subtree the head is in moves
+    /// the head to the hidden message's parent, so new messages stay visible.
+    pub fn hide_message(&mut self, message_id: &str, subtree: bool) -> Result<(), String> {
+        log(&format!(
+            "Hiding message {} (subtree: {})",
+            message_id, subtree
+        ));
+        let message = self.own_message(message_id)?;
+
+        self.moderation
+            .hidden
+            .insert(message_id.to_string(), subtree);
+        self.store_moderation()?;
+
+        if subtree
+            && self
+                .load_ancestors(self.head.clone())?
+                .iter()
+                .any(|id| id == message_id)
+        {
+            log(&format!(
+                "Head is inside the hidden subtree, moving it to {:?}",
+                message.parent_id
+            ));
+            self.set_head(message.parent_id)?;
+        }
+
+        self.audit_message_action(MessageAuditEntry {
+            timestamp: timing::now(),
+            action: "hide".to_string(),
+            message_id: message_id.to_string(),
+            subtree,
+            replacement_id: None,
+            relinked: 0,
+            original_removed: false,
+            previous: None,
+        });
+        self.broadcast(&ChatStateResponse::MessageHidden {
+            message_id: message_id.to_string(),
+            hidden: true,
+        });
+        Ok(())
+    }
+
+    pub fn unhide_message(&mut self, message_id: &str) -> Result<(), String> {
+        log(&format!("Unhiding message {}", message_id));
+        if self.moderation.hidden.remove(message_id).is_none() {
+            return Err(format!("Message {} is not hidden", message_id));
+        }
+        self.store_moderation()?;
+
+        self.audit_message_action(MessageAuditEntry {
+            timestamp: timing::now(),
+            action: "unhide".to_string(),
+            message_id: message_id.to_string(),
+            subtree: false,
+            replacement_id: None,
+            relinked: 0,
+            original_removed: false,
+            previous: None,
+        });
+        self.broadcast(&ChatStateResponse::MessageHidden {
+            message_id: message_id.to_string(),
+            hidden: false,
+        });
+        Ok(())
+    }
+
+    /// Store a message without moving the head, returning its id
+    fn store_message(&mut self, mut message: ChatMessage) -> Result<String, String> {
+        message.id = None;
+        let bytes = to_vec(&message).map_err(|e| format!("Failed to serialize message: {}", e))?;
+        let message_ref = store::store(&self.store_id, &bytes)
+            .map_err(|e| format!("Failed to store message: {}", e))?;
+
+        message.id = Some(message_ref.hash.clone());
+        self.messages.insert(message_ref.hash.clone(), message);
+        Ok(message_ref.hash)
+    }
+
+    /// Load every branch the head or its undo history points into
+    fn load_branches(&mut self) -> Result<(), String> {
+        let tips: Vec<String> = self
+            .head_history
+            .undo
+            .iter()
+            .chain(self.head_history.redo.iter())
+            .flatten()
+            .cloned()
+            .collect();
+        for tip in tips {
+            self.load_ancestors(Some(tip))?;
+        }
+        self.load_ancestors(self.head.clone())?;
+        Ok(())
+    }
+
+    /// A message of this conversation. Ids are hashes in a store shared with
+    /// other conversations, so only messages added here or on one of this
+    /// conversation's branches can be moderated.
+    fn own_message(&mut self, message_id: &str) -> Result<ChatMessage, String> {
+        self.load_branches()?;
+        self.messages
+            .get(message_id)
+            .cloned()
+            .ok_or_else(|| format!("Message {} not found", message_id))
+    }
+
+    /// Children of this conversation's messages, by parent id
+    fn message_children(&mut self) -> Result<HashMap<String, Vec<(String, ChatMessage)>>, String> {
+        self.load_branches()?;
+
+        let mut children: HashMap<String, Vec<(String, ChatMessage)>> = HashMap::new();
+        for (id, message) in &self.messages {
+            if let Some(parent_id) = &message.parent_id {
+                children
+                    .entry(parent_id.clone())
+                    .or_default()
+                    .push((id.clone(), message.clone()));
+            }
+        }
+        Ok(children)
+    }
+
+    /// Replace a message's content with a redaction marker. The message and
+    /// its descendants are stored again under new ids, and the head and undo
+    /// history follow them. Tool results it stored, cached results of its tool
+    /// calls and their audited arguments are dropped too. Returns the id of the
+    /// redacted copy.
+    pub fn redact_message(&mut self, message_id: &str) -> Result<String, String> {
+        log(&format!("Redacting message {}", message_id));
+
+        let original = self.own_message(message_id)?;
+        let children = self.message_children()?;
+
+        // Truncated tool results name their stored reference, which must not
+        // stay readable through read_tool_result
+        let original_text = serde_json::to_string(&original.entry).unwrap_or_default();
+        self.stored_tool_results
+            .retain(|reference| !original_text.contains(reference.as_str()));
+
+        // Calls the message made keep their arguments in the tool audit log,
+        // and calls it made or answered keep both in the tool cache
+        let answered: HashSet<&str> = entry_content(&original.entry)
+            .iter()
+            .filter_map(|content| match content {
+                MessageContent::ToolResult { tool_use_id, .. } => Some(tool_use_id.as_str()),
+                _ => None,
+            })
+            .collect();
+        let parent = original
+            .parent_id
+            .as_ref()
+            .and_then(|parent_id| self.messages.get(parent_id));
+        let answered_calls = parent
+            .map(|parent| tool_uses(&parent.entry))
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|(id, _, _)| answered.contains(id.as_str()));
+        let made_calls = tool_uses(&original.entry);
+        let made_ids: HashSet<String> = made_calls.iter().map(|(id, _, _)| id.clone()).collect();
+        let cache_keys: HashSet<String> = made_calls
+            .iter()
+            .cloned()
+            .chain(answered_calls)
+            .map(|(_, name, input)| cache_key(&name, &input))
+            .collect();
+        self.tool_cache.retain(|key, _| !cache_keys.contains(key));
+        if !made_ids.is_empty() {
+            let redacted = self.redact_tool_audit(&made_ids)?;
+            log(&format!(
+                "Cleared arguments of {} tool audit entries",
+                redacted
+            ));
+        }
+
+        let replacement_id = self.store_message(ChatMessage {
+            id: None,
+            parent_id: original.parent_id.clone(),
+            entry: redact_entry(original.entry),
+            metadata: original.metadata,
+        })?;
+
+        let mut replaced = HashMap::new();
+        replaced.insert(message_id.to_string(), replacement_id.clone());
+        let mut queue = vec![message_id.to_string()];
+        while let Some(old_parent) = queue.pop() {
+            let new_parent = replaced[&old_parent].clone();
+            for (old_id, child) in children.get(&old_parent).into_iter().flatten() {
+                let mut child = child.clone();
+                child.parent_id = Some(new_parent.clone());
+                let new_id = self.store_message(child)?;
+                replaced.insert(old_id.clone(), new_id);
+                queue.push(old_id.clone());
+            }
+        }
+        let relinked = replaced.len() - 1;
+
+        let remap = |id: &Option<String>| match id.as_ref().and_then(|id| replaced.get(id)) {
+            Some(new_id) => Some(new_id.clone()),
+            None => id.clone(),
+        };
+        let head = remap(&self.head);
+        for entry in self
+            .head_history
+            .undo
+            .iter_mut()
+            .chain(self.head_history.redo.iter_mut())
+        {
+            *entry = remap(entry);
+        }
+        let hidden = std::mem::take(&mut self.moderation.hidden);
+        self.moderation.hidden = hidden
+            .into_iter()
+            .map(|(id, subtree)| (replaced.get(&id).cloned().unwrap_or(id), subtree))
+            .collect();
+
+        self.messages.remove(message_id);
+        self.moderation.redacted.insert(message_id.to_string());
+        self.store_moderation()?;
+
+        if head != self.head {
+            self.head = head;
+            self.store_head()?;
+            self.broadcast(&ChatStateResponse::Head {
+                head: self.head.clone(),
+            });
+        }
+
+        self.audit_message_action(MessageAuditEntry {
+            timestamp: timing::now(),
+            action: "redact".to_string(),
+            message_id: message_id.to_string(),
+            subtree: false,
+            replacement_id: Some(replacement_id.clone()),
+            relinked,
+            original_removed: false,
+            previous: None,
+        });
+        self.broadcast(&ChatStateResponse::MessageRedacted {
+            message_id: message_id.to_string(),
+            replacement_id: replacement_id.clone(),
+        });
+
+        log(&format!(
+            "Redacted message {} as {}, relinked {} descendants",
+            message_id, replacement_id, relinked
+        ));
+        Ok(replacement_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Moderation;
+    use crate::state::{ChatEntry, ChatMessage};
+    use genai_types::messages::{Role, StopReason, Usage};
+    use genai_types::{CompletionResponse, Message, MessageContent};
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    fn message(id: &str, content: MessageContent) -> ChatMessage {
+        ChatMessage {
+            id: Some(id.to_string()),
+            parent_id: None,
+            entry: ChatEntry::Message(Message {
+                role: Role::User,
+                content: vec![content],
+            }),
+            metadata: None,
+        }
+    }
+
+    fn text(id: &str) -> ChatMessage {
+        message(
+            id,
+            MessageContent::Text {
+                text: id.to_string(),
+            },
+        )
+    }
+
+    fn tool_call(id: &str) -> ChatMessage {
+        ChatMessage {
+            id: Some(id.to_string()),
+            parent_id: None,
+            entry: ChatEntry::Completion(CompletionResponse {
+                content: vec![MessageContent::ToolUse {
+                    id: "call".to_string(),
+                    name: "search".to_string(),
+                    input: json!({}),
+                }],
+                id: id.to_string(),
+                model: "model".to_string(),
+                role: Role::Assistant,
+                stop_reason: StopReason::ToolUse,
+                stop_sequence: None,
+                message_type: "message".to_string(),
+                usage: Usage {
+                    input_tokens: 0,
+                    output_tokens: 0,
+                },
+            }),
+            metadata: None,
+        }
+    }
+
+    fn tool_result(id: &str) -> ChatMessage {
+        message(
+            id,
+            MessageContent::ToolResult {
+                tool_use_id: "call".to_string(),
+                content: vec![],
+                is_error: None,
+            },
+        )
+    }
+
+    fn visible_ids(hidden: &[(&str, bool)], chain: Vec<ChatMessage>) -> Vec<String> {
+        let moderation = Moderation {
+            hidden: hidden
+                .iter()
+                .map(|(id, subtree)| (id.to_string(), *subtree))
+                .collect::<HashMap<_, _>>(),
+            ..Default::default()
+        };
+        moderation
+            .visible_chain(chain)
+            .into_iter()
+            .filter_map(|m| m.id)
+            .collect()
+    }
+
+    #[test]
+    fn keeps_everything_when_nothing_is_hidden() {
+        assert_eq!(visible_ids(&[], vec![text("a"), text("b")]), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn drops_a_hidden_message_only() {
+        assert_eq!(
+            visible_ids(&[("b", false)], vec![text("a"), text("b"), text("c")]),
+            vec!["a", "c"]
+        );
+    }
+
+    #[test]
+    fn a_hidden_subtree_ends_the_chain() {
+        assert_eq!(
+            visible_ids(&[("b", true)], vec![text("a"), text("b"), text("c")]),
+            vec!["a"]
+        );
+    }
+
+    #[test]
+    fn hides_tool_calls_and_results_together() {
+        let chain = || vec![text("a"), tool_call("b"), tool_result("c"), text("d")];
+        assert_eq!(visible_ids(&[("b", false)], chain()), vec!["a", "d"]);
+        assert_eq!(visible_ids(&[("c", false)], chain()), vec!["a", "d"]);
+    }
+}
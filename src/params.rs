@@ -0,0 +1,86 @@
+use genai_types::ToolChoice;
+use serde::{Deserialize, Serialize};
+
+pub const TOOL_CHOICE: &str = "tool_choice";
+pub const DISABLE_PARALLEL_TOOL_USE: &str = "disable_parallel_tool_use";
+
+/// Parameters `CompletionRequest` has a field for. Sampling and thinking
+/// parameters such as `top_p` wait for a genai-types release that can carry
+/// them.
+const REQUEST_PARAMS: [&str; 2] = [TOOL_CHOICE, DISABLE_PARALLEL_TOOL_USE];
+
+/// Parameters each built-in provider supports. Other providers are only
+/// limited to `REQUEST_PARAMS`.
+///
+/// | parameter                   | anthropic | google |
+/// |-----------------------------|-----------|--------|
+/// | `tool_choice`               | yes       | yes    |
+/// | `disable_parallel_tool_use` | yes       | no     |
+pub fn supported_params(provider: &str) -> &'static [&'static str] {
+    match provider {
+        "google" => &[TOOL_CHOICE],
+        _ => &REQUEST_PARAMS,
+    }
+}
+
+/// Optional completion parameters. Unset parameters use the provider's default.
+/// Unknown parameters are rejected rather than silently dropped.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct CompletionParams {
+    /// Let the model decide, force any tool, force a named tool, or forbid tools
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<ToolChoice>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disable_parallel_tool_use: Option<bool>,
+}
+
+impl CompletionParams {
+    /// These parameters with every parameter set in `overrides` replaced
+    pub fn layered(&self, overrides: &CompletionParams) -> CompletionParams {
+        CompletionParams {
+            tool_choice: overrides
+                .tool_choice
+                .clone()
+                .or_else(|| self.tool_choice.clone()),
+            disable_parallel_tool_use: overrides
+                .disable_parallel_tool_use
+                .or(self.disable_parallel_tool_use),
+        }
+    }
+
+    /// Names of the parameters that are set
+    pub fn set_params(&self) -> Vec<&'static str> {
+        let mut set = Vec::new();
+        if self.tool_choice.is_some() {
+            set.push(TOOL_CHOICE);
+        }
+        if self.disable_parallel_tool_use.is_some() {
+            set.push(DISABLE_PARALLEL_TOOL_USE);
+        }
+        set
+    }
+
+    /// Set parameters that `provider` does not support
+    pub fn unsupported_by(&self, provider: &str) -> Vec<&'static str> {
+        let supported = supported_params(provider);
+        self.set_params()
+            .into_iter()
+            .filter(|param| !supported.contains(param))
+            .collect()
+    }
+
+    /// Unset the parameters `provider` does not support, returning their names
+    pub fn retain_supported(&mut self, provider: &str) -> Vec<&'static str> {
+        let unsupported = self.unsupported_by(provider);
+        for param in &unsupported {
+            match *param {
+                TOOL_CHOICE => self.tool_choice = None,
+                DISABLE_PARALLEL_TOOL_USE => self.disable_parallel_tool_use = None,
+                _ => {}
+            }
+        }
+        unsupported
+    }
+}
@@ -0,0 +1,113 @@
+use crate::bindings::theater::simple::runtime::log;
+use crate::state::{ChatState, ConversationSettings};
+use serde_json::{Map, Value};
+
+/// Apply an RFC 7386 JSON Merge Patch: objects merge recursively, `null`
+/// removes a field, and anything else (arrays included) replaces the target
+pub fn merge_patch(target: &mut Value, patch: &Value) {
+    let patch_map = match patch {
+        Value::Object(patch_map) => patch_map,
+        _ => {
+            *target = patch.clone();
+            return;
+        }
+    };
+
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    if let Value::Object(target_map) = target {
+        for (key, value) in patch_map {
+            if value.is_null() {
+                target_map.remove(key);
+            } else {
+                merge_patch(target_map.entry(key.clone()).or_insert(Value::Null), value);
+            }
+        }
+    }
+}
+
+impl ChatState {
+    /// Current settings with `patch` merged in. The MCP server section, which
+    /// carries runtime state, is kept as is unless the patch touches it.
+    pub fn patched_settings(&self, patch: &Value) -> Result<ConversationSettings, String> {
+        log(&format!("Patching settings with: {}", patch));
+
+        let mut settings = serde_json::to_value(&self.settings)
+            .map_err(|e| format!("Failed to serialize settings: {}", e))?;
+        merge_patch(&mut settings, patch);
+
+        let mut patched: ConversationSettings = serde_json::from_value(settings)
+            .map_err(|e| format!("Patched settings are invalid: {}", e))?;
+
+        if patch.get("mcp_servers").is_none() {
+            patched.mcp_servers = self.settings.mcp_servers.clone();
+        }
+
+        Ok(patched)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::merge_patch;
+    use serde_json::{json, Value};
+
+    fn patched(mut target: Value, patch: Value) -> Value {
+        merge_patch(&mut target, &patch);
+        target
+    }
+
+    #[test]
+    fn null_removes_fields() {
+        assert_eq!(
+            patched(json!({"a": 1, "b": 2}), json!({"a": null})),
+            json!({"b": 2})
+        );
+        assert_eq!(
+            patched(json!({"a": 1}), json!({"missing": null})),
+            json!({"a": 1})
+        );
+    }
+
+    #[test]
+    fn merges_nested_objects() {
+        assert_eq!(
+            patched(
+                json!({"model_config": {"model": "a", "provider": "p"}, "title": "t"}),
+                json!({"model_config": {"model": "b"}})
+            ),
+            json!({"model_config": {"model": "b", "provider": "p"}, "title": "t"})
+        );
+        assert_eq!(
+            patched(
+                json!({"a": {"b": {"c": 1, "d": 2}}}),
+                json!({"a": {"b": {"d": null}}})
+            ),
+            json!({"a": {"b": {"c": 1}}})
+        );
+    }
+
+    #[test]
+    fn replaces_arrays_and_scalars() {
+        assert_eq!(
+            patched(json!({"a": [1, 2, 3]}), json!({"a": [4]})),
+            json!({"a": [4]})
+        );
+        assert_eq!(patched(json!({"a": "x"}), json!({"a": 5})), json!({"a": 5}));
+        assert_eq!(
+            patched(json!({"a": {"b": 1}}), json!({"a": true})),
+            json!({"a": true})
+        );
+        assert_eq!(patched(json!({"a": 1}), json!([1, 2])), json!([1, 2]));
+    }
+
+    #[test]
+    fn patches_non_objects_as_empty_objects() {
+        assert_eq!(patched(json!("x"), json!({"a": 1})), json!({"a": 1}));
+        assert_eq!(
+            patched(json!({"a": 1}), json!({"a": {"b": null}})),
+            json!({"a": {}})
+        );
+    }
+}
@@ -0,0 +1,117 @@
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store;
+use crate::patch::merge_patch;
+use crate::state::{ChatState, ConversationSettings, DEFAULT_TITLE};
+use serde_json::{to_vec, Value};
+
+/// Presets are shared by every conversation in a store
+const PRESET_LABEL_PREFIX: &str = "preset_";
+
+/// Settings that describe one conversation rather than how it runs. Presets
+/// neither save nor overwrite them.
+const CONVERSATION_FIELDS: [&str; 3] = ["title", "title_settled", "pinned_note"];
+
+fn preset_label(name: &str) -> Result<String, String> {
+    if name.trim().is_empty() {
+        return Err("Preset name must not be empty".to_string());
+    }
+    Ok(format!("{}{}", PRESET_LABEL_PREFIX, name))
+}
+
+fn load_preset_value(store_id: &str, name: &str) -> Result<Value, String> {
+    log(&format!("Loading settings preset: {}", name));
+
+    let preset_ref = store::get_by_label(store_id, &preset_label(name)?)
+        .map_err(|e| format!("Failed to look up preset {}: {}", name, e))?
+        .ok_or_else(|| format!("Preset {} not found", name))?;
+    let preset_bytes = store::get(store_id, &preset_ref)
+        .map_err(|e| format!("Failed to read preset {}: {}", name, e))?;
+    serde_json::from_slice(&preset_bytes)
+        .map_err(|e| format!("Failed to parse preset {}: {}", name, e))
+}
+
+fn preset_with_overrides(
+    name: &str,
+    mut settings: Value,
+    overrides: Option<&Value>,
+) -> Result<ConversationSettings, String> {
+    if let Some(overrides) = overrides {
+        merge_patch(&mut settings, overrides);
+    }
+
+    serde_json::from_value(settings)
+        .map_err(|e| format!("Preset {} with overrides is invalid: {}", name, e))
+}
+
+/// Settings saved under a preset, with `overrides` merged in as a JSON Merge Patch
+pub fn load_preset(
+    store_id: &str,
+    name: &str,
+    overrides: Option<&Value>,
+) -> Result<ConversationSettings, String> {
+    let settings = load_preset_value(store_id, name)?;
+    preset_with_overrides(name, settings, overrides)
+}
+
+impl ChatState {
+    /// Settings from a preset for this conversation, keeping its title and
+    /// pinned note unless `overrides` set them
+    pub fn preset_settings(
+        &self,
+        name: &str,
+        overrides: Option<&Value>,
+    ) -> Result<ConversationSettings, String> {
+        let mut settings = load_preset_value(&self.store_id, name)?;
+        let current = serde_json::to_value(&self.settings)
+            .map_err(|e| format!("Failed to serialize settings: {}", e))?;
+        if let Value::Object(fields) = &mut settings {
+            for field in CONVERSATION_FIELDS {
+                match current.get(field) {
+                    Some(value) => fields.insert(field.to_string(), value.clone()),
+                    None => fields.remove(field),
+                };
+            }
+        }
+        preset_with_overrides(name, settings, overrides)
+    }
+
+    /// Save settings under a preset name, replacing any preset with that name.
+    /// Runtime state and `CONVERSATION_FIELDS` are left out.
+    pub fn save_preset(&self, name: &str, settings: &ConversationSettings) -> Result<(), String> {
+        log(&format!("Saving settings preset: {}", name));
+
+        let mut settings = settings.clone();
+        settings.title = DEFAULT_TITLE.to_string();
+        settings.title_settled = false;
+        settings.pinned_note = None;
+        for mcp in &mut settings.mcp_servers {
+            mcp.actor_id = None;
+            mcp.tools = None;
+            mcp.restart_count = 0;
+        }
+
+        let settings_bytes =
+            to_vec(&settings).map_err(|e| format!("Failed to serialize preset: {}", e))?;
+        store::store_at_label(&self.store_id, &preset_label(name)?, &settings_bytes)
+            .map_err(|e| format!("Failed to store preset {}: {}", name, e))?;
+        Ok(())
+    }
+
+    pub fn list_presets(&self) -> Result<Vec<String>, String> {
+        let labels = store::list_labels(&self.store_id)
+            .map_err(|e| format!("Failed to list presets: {}", e))?;
+        let mut names: Vec<String> = labels
+            .iter()
+            .filter_map(|label| label.strip_prefix(PRESET_LABEL_PREFIX))
+            .map(|name| name.to_string())
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+
+    pub fn delete_preset(&self, name: &str) -> Result<(), String> {
+        log(&format!("Deleting settings preset: {}", name));
+        store::remove_label(&self.store_id, &preset_label(name)?)
+            .map_err(|e| format!("Failed to delete preset {}: {}", name, e))
+    }
+}
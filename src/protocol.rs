@@ -1,5 +1,11 @@
+use crate::audit::ToolAuditEntry;
+use crate::moderation::MessageAuditEntry;
+use crate::proxy::{ProviderConfig, ProviderStatus};
+use crate::settings_history::SettingsVersion;
 use crate::state::ChatMessage;
 use genai_types::{Message, ModelInfo};
+use mcp_protocol::prompt::Prompt;
+use mcp_protocol::resource::{Resource, ResourceContent};
 use mcp_protocol::tool::Tool;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -13,6 +19,13 @@ use crate::state::ConversationSettings;
 pub enum McpActorRequest {
     ToolsList {},
     ToolsCall { name: String, args: Value },
+    ResourcesList {},
+    ResourcesRead { uri: String },
+    PromptsList {},
+    PromptsGet {
+        name: String,
+        args: Option<HashMap<String, String>>,
+    },
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -33,6 +46,33 @@ pub struct McpError {
     pub data: Option<Value>,
 }
 
+/// A resource published by an MCP server
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ServerResource {
+    /// Name or actor id of the server that publishes the resource
+    pub server: String,
+    pub resource: Resource,
+}
+
+/// A prompt template published by an MCP server
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ServerPrompt {
+    /// Name or actor id of the server that publishes the prompt
+    pub server: String,
+    pub prompt: Prompt,
+}
+
+/// JSON-RPC notification sent by an MCP actor, e.g. `notifications/tools/list_changed`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct McpNotification {
+    pub jsonrpc: String,
+    pub method: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub params: Option<Value>,
+}
+
+pub const TOOLS_LIST_CHANGED: &str = "notifications/tools/list_changed";
+
 /// Messages received by the chat-state actor
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(tag = "type")]
@@ -47,24 +87,107 @@ pub enum ChatStateRequest {
     #[serde(rename = "get_settings")]
     GetSettings,
     #[serde(rename = "update_settings")]
-    UpdateSettings { settings: ConversationSettings },
+    UpdateSettings { settings: Box<ConversationSettings> },
+    /// JSON Merge Patch against the current settings
+    #[serde(rename = "patch_settings")]
+    PatchSettings { patch: Value },
+    /// Save the given settings, or the current ones, as a named preset
+    #[serde(rename = "save_preset")]
+    SavePreset {
+        name: String,
+        settings: Option<Box<ConversationSettings>>,
+    },
+    #[serde(rename = "list_presets")]
+    ListPresets,
+    #[serde(rename = "delete_preset")]
+    DeletePreset { name: String },
+    /// Switch to a preset, with `overrides` merged on top
+    #[serde(rename = "apply_preset")]
+    ApplyPreset {
+        name: String,
+        overrides: Option<Value>,
+    },
+    /// Render `template`, or the configured system prompt, without sending it
+    #[serde(rename = "preview_system_prompt")]
+    PreviewSystemPrompt { template: Option<String> },
+    #[serde(rename = "regenerate_title")]
+    RegenerateTitle,
+    #[serde(rename = "get_settings_history")]
+    GetSettingsHistory,
+    #[serde(rename = "rollback_settings")]
+    RollbackSettings { version: String },
 
     #[serde(rename = "get_head")]
     GetHead,
     #[serde(rename = "set_head")]
     SetHead { head: Option<String> },
 
+    /// Move the head back to where it was before its last move
+    #[serde(rename = "undo")]
+    Undo,
+    /// Reapply the last head move undone by `Undo`
+    #[serde(rename = "redo")]
+    Redo,
+
     #[serde(rename = "get_history")]
     GetHistory,
     #[serde(rename = "get_message")]
     GetMessage { message_id: String },
     #[serde(rename = "get_metadata")]
     GetMetadata,
+    /// Hide a message, and with `subtree` everything after it, from the
+    /// history and from the model
+    #[serde(rename = "hide_message")]
+    HideMessage {
+        message_id: String,
+        #[serde(default)]
+        subtree: bool,
+    },
+    #[serde(rename = "unhide_message")]
+    UnhideMessage { message_id: String },
+    /// Replace a message's content with a redaction marker
+    #[serde(rename = "redact_message")]
+    RedactMessage { message_id: String },
+    #[serde(rename = "get_message_audit")]
+    GetMessageAudit { since: Option<u64> },
 
     #[serde(rename = "list_models")]
     ListModels,
+    /// Add a model provider, or replace the one with the same name
+    #[serde(rename = "add_provider")]
+    AddProvider { provider: ProviderConfig },
+    #[serde(rename = "remove_provider")]
+    RemoveProvider { name: String },
+    #[serde(rename = "get_provider_status")]
+    GetProviderStatus,
     #[serde(rename = "list_tools")]
     ListTools,
+    #[serde(rename = "refresh_tools")]
+    RefreshTools,
+    #[serde(rename = "clear_tool_cache")]
+    ClearToolCache,
+    #[serde(rename = "get_tool_audit")]
+    GetToolAudit {
+        since: Option<u64>,
+        tool: Option<String>,
+    },
+
+    #[serde(rename = "list_resources")]
+    ListResources,
+    #[serde(rename = "read_resource")]
+    ReadResource { server: Option<String>, uri: String },
+    /// Attach a resource to the next user message
+    #[serde(rename = "attach_resource")]
+    AttachResource { server: Option<String>, uri: String },
+    #[serde(rename = "list_prompts")]
+    ListPrompts,
+    /// Expand a prompt template into messages
+    #[serde(rename = "get_prompt")]
+    GetPrompt {
+        server: Option<String>,
+        name: String,
+        arguments: Option<HashMap<String, String>>,
+    },
 }
 
 /// Data associated with the response
@@ -81,10 +204,10 @@ pub enum ChatStateResponse {
     Head { head: Option<String> },
 
     #[serde(rename = "chat_message")]
-    ChatMessage { message: ChatMessage },
+    ChatMessage { message: Box<ChatMessage> },
 
     #[serde(rename = "settings")]
-    Settings { settings: ConversationSettings },
+    Settings { settings: Box<ConversationSettings> },
 
     #[serde(rename = "error")]
     Error { error: ErrorInfo },
@@ -92,8 +215,59 @@ pub enum ChatStateResponse {
     #[serde(rename = "tools_list")]
     ToolsList { tools: Vec<Tool> },
 
+    #[serde(rename = "resources_list")]
+    ResourcesList { resources: Vec<ServerResource> },
+
+    #[serde(rename = "resource_contents")]
+    ResourceContents { contents: Vec<ResourceContent> },
+
+    #[serde(rename = "prompts_list")]
+    PromptsList { prompts: Vec<ServerPrompt> },
+
+    #[serde(rename = "prompt_messages")]
+    PromptMessages { messages: Vec<Message> },
+
+    /// Sent to subscribers when the title changes, and in reply to `regenerate_title`
+    #[serde(rename = "title_changed")]
+    TitleChanged { title: String },
+
+    #[serde(rename = "system_prompt")]
+    SystemPrompt { text: Option<String> },
+
+    #[serde(rename = "presets_list")]
+    PresetsList { names: Vec<String> },
+
+    #[serde(rename = "settings_history")]
+    SettingsHistory { versions: Vec<SettingsVersion> },
+
+    #[serde(rename = "tool_audit")]
+    ToolAudit { entries: Vec<ToolAuditEntry> },
+
+    /// Sent to subscribers when a message is hidden or unhidden
+    #[serde(rename = "message_hidden")]
+    MessageHidden { message_id: String, hidden: bool },
+
+    /// Sent to subscribers when a message is redacted, and in reply to `redact_message`
+    #[serde(rename = "message_redacted")]
+    MessageRedacted {
+        message_id: String,
+        replacement_id: String,
+    },
+
+    #[serde(rename = "message_audit")]
+    MessageAudit { entries: Vec<MessageAuditEntry> },
+
+    #[serde(rename = "provider_status")]
+    ProviderStatus { providers: Vec<ProviderStatus> },
+
     #[serde(rename = "models_list")]
-    ModelsList { models: Vec<ModelInfo> },
+    ModelsList {
+        models: Vec<ModelInfo>,
+
+        /// Providers whose models could not be listed, with the error
+        #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+        errors: HashMap<String, String>,
+    },
 
     #[serde(rename = "metadata")]
     Metadata {
@@ -126,6 +300,21 @@ pub fn create_error_response(code: &str, message: &str) -> ChatStateResponse {
     }
 }
 
+/// Create an error response naming each offending field in `details`
+pub fn create_error_response_with_details(
+    code: &str,
+    message: &str,
+    details: HashMap<String, String>,
+) -> ChatStateResponse {
+    ChatStateResponse::Error {
+        error: ErrorInfo {
+            code: code.to_string(),
+            message: message.to_string(),
+            details: Some(details),
+        },
+    }
+}
+
 /// Convert internal settings to client-compatible settings
 pub fn internal_to_client_settings(
     settings: &crate::state::ConversationSettings,
@@ -1,42 +1,326 @@
 use crate::bindings::theater::simple::message_server_host;
 use crate::bindings::theater::simple::runtime::log;
-use crate::bindings::theater::simple::supervisor::spawn;
+use crate::bindings::theater::simple::supervisor::{
+    list_children, restart_child, spawn, stop_child,
+};
+use crate::bindings::theater::simple::timing;
+use crate::{ANTHROPIC_PROXY_MANIFEST, GOOGLE_PROXY_MANIFEST};
 use genai_types::{ProxyRequest, ProxyResponse};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
+/// A model provider and the proxy actor that serves it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProviderConfig {
+    /// Provider name, matched against `ModelConfig::provider`
+    pub name: String,
+
+    /// Manifest of the proxy actor, as a URL or local path
+    pub manifest_path: String,
+
+    /// Init state passed to the proxy actor
+    #[serde(default)]
+    pub init_state: Option<Value>,
+}
+
+/// Providers used when settings don't list any
+pub fn default_providers() -> Vec<ProviderConfig> {
+    vec![
+        ProviderConfig {
+            name: "anthropic".to_string(),
+            manifest_path: ANTHROPIC_PROXY_MANIFEST.to_string(),
+            init_state: None,
+        },
+        ProviderConfig {
+            name: "google".to_string(),
+            manifest_path: GOOGLE_PROXY_MANIFEST.to_string(),
+            init_state: None,
+        },
+    ]
+}
+
+/// A provider's proxy actor, spawned on first use
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Proxy {
     name: String,
-    actor_id: String,
+
+    /// Actor id while the proxy is running
+    actor_id: Option<String>,
+
+    /// Config to spawn the proxy from
+    #[serde(default)]
+    config: Option<ProviderConfig>,
+
+    /// `timing::now()` of the last request sent to the proxy
+    #[serde(default)]
+    last_used: Option<u64>,
+
+    /// Outcomes of the most recent requests, newest last (true = success)
+    #[serde(default)]
+    recent_outcomes: Vec<bool>,
+
+    #[serde(default)]
+    last_error: Option<String>,
+
+    /// Restarts and respawns after a crash since the proxy was created from settings
+    #[serde(default)]
+    restarts: u32,
+
+    /// Set once the proxy exceeded `MAX_PROXY_RESTARTS`. It is not spawned
+    /// again until settings are synced.
+    #[serde(default)]
+    failed: bool,
+}
+
+/// Number of recent requests used to compute a proxy's error rate
+const OUTCOME_WINDOW: usize = 20;
+const MAX_PROXY_RESTARTS: u32 = 3;
+
+/// Health of a provider's proxy, as reported by `get_provider_status`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProviderStatus {
+    pub name: String,
+    pub actor_id: Option<String>,
+
+    /// Whether the proxy actor is currently among our children
+    pub alive: bool,
+    pub restarts: u32,
+
+    /// Whether the proxy gave up after too many restarts
+    pub failed: bool,
+    pub last_used: Option<u64>,
+
+    /// Failed share of the last requests, from 0.0 to 1.0
+    pub error_rate: f32,
+    pub recent_requests: usize,
+    pub last_error: Option<String>,
 }
 
 impl Proxy {
-    pub fn new(name: &str, manifest_path: &str) -> Result<Self, String> {
+    /// Create a proxy for the provider without spawning it yet
+    pub fn new(config: &ProviderConfig) -> Self {
+        Proxy {
+            name: config.name.clone(),
+            actor_id: None,
+            config: Some(config.clone()),
+            last_used: None,
+            recent_outcomes: Vec::new(),
+            last_error: None,
+            restarts: 0,
+            failed: false,
+        }
+    }
+
+    pub fn actor_id(&self) -> Option<&str> {
+        self.actor_id.as_deref()
+    }
+
+    /// Whether the proxy actor is running as one of our children
+    fn is_alive(&self) -> bool {
+        match &self.actor_id {
+            Some(actor_id) => list_children().contains(actor_id),
+            None => false,
+        }
+    }
+
+    /// Actor id of the proxy, spawning it if it is not running
+    fn ensure_started(&mut self) -> Result<String, String> {
+        if self.failed {
+            return Err(format!(
+                "Proxy {} failed after {} restarts",
+                self.name, MAX_PROXY_RESTARTS
+            ));
+        }
+        if let Some(actor_id) = &self.actor_id {
+            return Ok(actor_id.clone());
+        }
+
+        if self.config.is_none() {
+            // Proxies from before providers were configurable carry no config
+            self.config = default_providers()
+                .into_iter()
+                .find(|p| p.name == self.name);
+        }
+        let config = self
+            .config
+            .as_ref()
+            .ok_or_else(|| format!("No provider config to spawn proxy {}", self.name))?;
+
+        log(&format!(
+            "Spawning proxy for provider {} from {}",
+            config.name, config.manifest_path
+        ));
+
+        let init_state = match &config.init_state {
+            Some(state) => Some(
+                serde_json::to_vec(state)
+                    .map_err(|e| format!("Failed to serialize proxy init state: {}", e))?,
+            ),
+            None => None,
+        };
+
         // Spawn the proxy actor using the manifest path
-        let actor_id = spawn(manifest_path, None)
+        let actor_id = spawn(&config.manifest_path, init_state.as_deref())
             .map_err(|e| format!("Failed to spawn proxy actor: {}", e))?;
 
-        Ok(Proxy {
-            name: name.to_string(),
-            actor_id,
-        })
+        self.actor_id = Some(actor_id.clone());
+        Ok(actor_id)
+    }
+
+    /// Count a restart, marking the proxy failed and stopping it once
+    /// `MAX_PROXY_RESTARTS` is used up. Returns whether it may restart.
+    fn count_restart(&mut self) -> bool {
+        if self.restarts >= MAX_PROXY_RESTARTS {
+            log(&format!(
+                "Proxy {} exceeded {} restarts, giving up on it",
+                self.name, MAX_PROXY_RESTARTS
+            ));
+            self.stop();
+            self.failed = true;
+            return false;
+        }
+
+        self.restarts += 1;
+        log(&format!(
+            "Restarting proxy {} (restart {}/{})",
+            self.name, self.restarts, MAX_PROXY_RESTARTS
+        ));
+        true
+    }
+
+    /// Restart the proxy after it crashed or exited. If the restart fails the
+    /// actor is dropped and spawned again on next use.
+    pub fn restart(&mut self) {
+        let actor_id = match &self.actor_id {
+            Some(actor_id) => actor_id.clone(),
+            None => return,
+        };
+
+        if !self.count_restart() {
+            return;
+        }
+        if let Err(e) = restart_child(&actor_id) {
+            log(&format!("Failed to restart proxy {}: {}", self.name, e));
+            self.actor_id = None;
+        }
+    }
+
+    /// Whether the proxy gave up after too many restarts
+    pub fn has_failed(&self) -> bool {
+        self.failed
     }
 
-    /// Sends a request to the anthropic-proxy actor and returns the response
-    pub fn send_to_proxy(&self, request: ProxyRequest) -> Result<ProxyResponse, String> {
+    fn record_outcome(&mut self, error: Option<String>) {
+        self.recent_outcomes.push(error.is_none());
+        if self.recent_outcomes.len() > OUTCOME_WINDOW {
+            self.recent_outcomes.remove(0);
+        }
+        if error.is_some() {
+            self.last_error = error;
+        }
+    }
+
+    pub fn status(&self) -> ProviderStatus {
+        let failures = self.recent_outcomes.iter().filter(|ok| !**ok).count();
+        let error_rate = if self.recent_outcomes.is_empty() {
+            0.0
+        } else {
+            failures as f32 / self.recent_outcomes.len() as f32
+        };
+
+        ProviderStatus {
+            name: self.name.clone(),
+            actor_id: self.actor_id.clone(),
+            alive: self.is_alive(),
+            restarts: self.restarts,
+            failed: self.failed,
+            last_used: self.last_used,
+            error_rate,
+            recent_requests: self.recent_outcomes.len(),
+            last_error: self.last_error.clone(),
+        }
+    }
+
+    /// Whether this proxy was created from the given config
+    pub fn matches_config(&self, config: &ProviderConfig) -> bool {
+        match &self.config {
+            Some(current) => current == config,
+            // Proxies spawned before providers were configurable
+            None => self.name == config.name,
+        }
+    }
+
+    /// Record the config a proxy from before providers were configurable was
+    /// matched to, so it can be respawned after being stopped
+    pub fn adopt_config(&mut self, config: &ProviderConfig) {
+        if self.config.is_none() {
+            self.config = Some(config.clone());
+        }
+    }
+
+    /// Whether the proxy is running and has not been used for `idle_ms`. A
+    /// proxy without a config is never idle, since it might not respawn.
+    pub fn is_idle(&self, now: u64, idle_ms: u64) -> bool {
+        self.actor_id.is_some()
+            && self.config.is_some()
+            && self
+                .last_used
+                .is_none_or(|last_used| now.saturating_sub(last_used) > idle_ms)
+    }
+
+    /// Stop the proxy actor if it is running. It is spawned again on next use.
+    pub fn stop(&mut self) {
+        if let Some(actor_id) = self.actor_id.take() {
+            log(&format!("Stopping proxy for provider {}", self.name));
+            if let Err(e) = stop_child(&actor_id) {
+                log(&format!("Failed to stop proxy {}: {}", self.name, e));
+            }
+        }
+    }
+
+    /// Sends a request to the proxy actor, spawning it if needed, and returns the response
+    pub fn send_to_proxy(&mut self, request: ProxyRequest) -> Result<ProxyResponse, String> {
         log(&format!("Sending request to proxy actor: {}", self.name));
 
         // Serialize the request
         let request_bytes = serde_json::to_vec(&request)
             .map_err(|e| format!("Failed to serialize proxy request: {}", e))?;
+        self.send_bytes(&request_bytes)
+    }
+
+    fn send_bytes(&mut self, request_bytes: &[u8]) -> Result<ProxyResponse, String> {
+        let actor_id = self.ensure_started()?;
+        self.last_used = Some(timing::now());
+
+        // Crashes are normally reported through the supervisor handlers, so
+        // liveness is only checked when a request could not be delivered
+        let mut result = message_server_host::request(&actor_id, request_bytes);
+        if result.is_err() && !self.is_alive() {
+            log(&format!(
+                "Proxy {} ({}) is no longer running",
+                self.name, actor_id
+            ));
+            self.actor_id = None;
+            if self.count_restart() {
+                let actor_id = self.ensure_started()?;
+                result = message_server_host::request(&actor_id, request_bytes);
+            }
+        }
 
-        let response_bytes = message_server_host::request(&self.actor_id, &request_bytes)
-            .map_err(|e| format!("Failed to send request to proxy: {}", e))?;
+        let response = result
+            .map_err(|e| format!("Failed to send request to proxy: {}", e))
+            .and_then(|response_bytes| {
+                // Parse the response
+                serde_json::from_slice::<ProxyResponse>(&response_bytes)
+                    .map_err(|e| format!("Failed to parse proxy response: {}", e))
+            });
 
-        // Parse the response
-        let response: ProxyResponse = serde_json::from_slice(&response_bytes)
-            .map_err(|e| format!("Failed to parse proxy response: {}", e))?;
+        match &response {
+            Ok(ProxyResponse::Error { error }) => self.record_outcome(Some(error.clone())),
+            Ok(_) => self.record_outcome(None),
+            Err(e) => self.record_outcome(Some(e.clone())),
+        }
 
-        Ok(response)
+        response
     }
 }
@@ -0,0 +1,194 @@
+use crate::bindings::theater::simple::runtime::log;
+use crate::protocol::{McpActorRequest, ServerPrompt, ServerResource};
+use crate::state::{ChatState, McpServer};
+use genai_types::messages::Role;
+use genai_types::{Message, MessageContent};
+use mcp_protocol::prompt::{Prompt, PromptGetResult, PromptMessageContent};
+use mcp_protocol::resource::{Resource, ResourceContent, ResourceReadResult};
+use serde_json::Value;
+use std::collections::HashMap;
+
+impl ChatState {
+    /// Running MCP servers, optionally restricted to the one with the given label
+    fn mcp_servers_matching(&self, server: &Option<String>) -> Vec<&McpServer> {
+        self.settings
+            .mcp_servers
+            .iter()
+            .filter(|mcp| mcp.actor_id.is_some())
+            .filter(|mcp| server.is_none() || mcp.label() == *server)
+            .collect()
+    }
+
+    /// List the resources published by every running MCP server
+    pub fn list_resources(&self) -> Result<Vec<ServerResource>, String> {
+        log("Listing resources from MCP servers");
+
+        let mut resources = Vec::new();
+        for mcp in self.mcp_servers_matching(&None) {
+            let label = mcp.label().unwrap_or_default();
+            match mcp.request(&McpActorRequest::ResourcesList {}) {
+                Ok(result) => {
+                    let listed = parse_field::<Vec<Resource>>(result, "resources")?;
+                    resources.extend(listed.into_iter().map(|resource| ServerResource {
+                        server: label.clone(),
+                        resource,
+                    }));
+                }
+                // Servers without resource support are skipped
+                Err(e) => log(&format!("Failed to list resources from {}: {}", label, e)),
+            }
+        }
+
+        Ok(resources)
+    }
+
+    /// Read a resource, from the named server or from the first server that has it
+    pub fn read_resource(
+        &self,
+        server: &Option<String>,
+        uri: &str,
+    ) -> Result<Vec<ResourceContent>, String> {
+        log(&format!("Reading resource: {}", uri));
+
+        let mut last_error = format!("No MCP server available to read {}", uri);
+        for mcp in self.mcp_servers_matching(server) {
+            let request = McpActorRequest::ResourcesRead {
+                uri: uri.to_string(),
+            };
+            match mcp.request(&request) {
+                Ok(result) => {
+                    let read: ResourceReadResult = serde_json::from_value(result)
+                        .map_err(|e| format!("Failed to parse resource contents: {}", e))?;
+                    return Ok(read.contents);
+                }
+                Err(e) => last_error = e,
+            }
+        }
+
+        Err(last_error)
+    }
+
+    /// Read a resource and queue it to be sent with the next user message
+    pub fn attach_resource(&mut self, server: &Option<String>, uri: &str) -> Result<(), String> {
+        let contents = self.read_resource(server, uri)?;
+        self.pending_attachments
+            .extend(contents.iter().map(resource_to_content));
+        log(&format!(
+            "Attached resource {}, {} attachment(s) pending",
+            uri,
+            self.pending_attachments.len()
+        ));
+        Ok(())
+    }
+
+    /// Prepend any pending attachments to a user message
+    pub fn take_attachments(&mut self, mut message: Message) -> Message {
+        if self.pending_attachments.is_empty() || !matches!(message.role, Role::User) {
+            return message;
+        }
+
+        let mut content = std::mem::take(&mut self.pending_attachments);
+        content.append(&mut message.content);
+        message.content = content;
+        message
+    }
+
+    /// List the prompt templates published by every running MCP server
+    pub fn list_prompts(&self) -> Result<Vec<ServerPrompt>, String> {
+        log("Listing prompts from MCP servers");
+
+        let mut prompts = Vec::new();
+        for mcp in self.mcp_servers_matching(&None) {
+            let label = mcp.label().unwrap_or_default();
+            match mcp.request(&McpActorRequest::PromptsList {}) {
+                Ok(result) => {
+                    let listed = parse_field::<Vec<Prompt>>(result, "prompts")?;
+                    prompts.extend(listed.into_iter().map(|prompt| ServerPrompt {
+                        server: label.clone(),
+                        prompt,
+                    }));
+                }
+                Err(e) => log(&format!("Failed to list prompts from {}: {}", label, e)),
+            }
+        }
+
+        Ok(prompts)
+    }
+
+    /// Expand a prompt template into conversation messages
+    pub fn get_prompt(
+        &self,
+        server: &Option<String>,
+        name: &str,
+        arguments: Option<HashMap<String, String>>,
+    ) -> Result<Vec<Message>, String> {
+        log(&format!("Getting prompt: {}", name));
+
+        let mut last_error = format!("No MCP server available for prompt {}", name);
+        for mcp in self.mcp_servers_matching(server) {
+            let request = McpActorRequest::PromptsGet {
+                name: name.to_string(),
+                args: arguments.clone(),
+            };
+            match mcp.request(&request) {
+                Ok(result) => {
+                    let prompt: PromptGetResult = serde_json::from_value(result)
+                        .map_err(|e| format!("Failed to parse prompt: {}", e))?;
+                    return Ok(prompt
+                        .messages
+                        .into_iter()
+                        .map(|m| Message {
+                            role: match m.role.as_str() {
+                                "assistant" => Role::Assistant,
+                                _ => Role::User,
+                            },
+                            content: vec![prompt_content_to_content(m.content)],
+                        })
+                        .collect());
+                }
+                Err(e) => last_error = e,
+            }
+        }
+
+        Err(last_error)
+    }
+}
+
+fn parse_field<T: serde::de::DeserializeOwned>(result: Value, field: &str) -> Result<T, String> {
+    let value = result
+        .get(field)
+        .cloned()
+        .ok_or_else(|| format!("No '{}' field in MCP response", field))?;
+    serde_json::from_value(value).map_err(|e| format!("Failed to parse {}: {}", field, e))
+}
+
+/// Render resource contents as text the model can read
+fn resource_to_content(resource: &ResourceContent) -> MessageContent {
+    let body = match (&resource.text, &resource.blob) {
+        (Some(text), _) => text.clone(),
+        (None, Some(blob)) => format!("[binary content, {} bytes base64]", blob.len()),
+        (None, None) => String::new(),
+    };
+
+    MessageContent::Text {
+        text: format!(
+            "<resource uri=\"{}\" mimeType=\"{}\">\n{}\n</resource>",
+            resource.uri, resource.mime_type, body
+        ),
+    }
+}
+
+fn prompt_content_to_content(content: PromptMessageContent) -> MessageContent {
+    match content {
+        PromptMessageContent::Text { text } => MessageContent::Text { text },
+        PromptMessageContent::Image { mime_type, data } => MessageContent::Text {
+            text: format!("[image {}, {} bytes base64]", mime_type, data.len()),
+        },
+        PromptMessageContent::Resource { resource } => resource_to_content(&ResourceContent {
+            uri: resource.uri,
+            mime_type: resource.mime_type,
+            text: resource.text,
+            blob: resource.data,
+        }),
+    }
+}
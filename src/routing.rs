@@ -0,0 +1,97 @@
+use crate::bindings::theater::simple::runtime::log;
+use crate::state::{ChatEntry, ChatMessage, ChatState, ModelConfig};
+use genai_types::messages::Role;
+use genai_types::MessageContent;
+use serde::{Deserialize, Serialize};
+
+/// Rough characters-per-token ratio used to estimate prompt size
+const CHARS_PER_TOKEN: usize = 4;
+
+/// A predicate on the conversation as it is about to be sent to the model
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type")]
+pub enum RouteCondition {
+    /// The estimated prompt size is above `tokens`
+    #[serde(rename = "prompt_tokens_above")]
+    PromptTokensAbove { tokens: usize },
+
+    /// The last message only carries tool results back to the model
+    #[serde(rename = "tool_result_follow_up")]
+    ToolResultFollowUp,
+
+    /// No completion has been generated in this chain yet
+    #[serde(rename = "first_turn")]
+    FirstTurn,
+}
+
+/// Use `model_config` when every condition matches
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RoutingRule {
+    pub conditions: Vec<RouteCondition>,
+    pub model_config: ModelConfig,
+}
+
+/// Estimate the prompt size in tokens from the serialized chain and system prompt
+pub fn estimate_prompt_tokens(chain: &[ChatMessage], system_prompt: Option<&str>) -> usize {
+    let chain_chars: usize = chain
+        .iter()
+        .map(|m| {
+            serde_json::to_string(&m.entry)
+                .map(|s| s.len())
+                .unwrap_or(0)
+        })
+        .sum();
+    let system_chars = system_prompt.map(str::len).unwrap_or(0);
+    (chain_chars + system_chars) / CHARS_PER_TOKEN
+}
+
+fn is_tool_result_follow_up(chain: &[ChatMessage]) -> bool {
+    match chain.last().map(|m| &m.entry) {
+        Some(ChatEntry::Message(message)) => {
+            matches!(message.role, Role::User)
+                && !message.content.is_empty()
+                && message
+                    .content
+                    .iter()
+                    .all(|c| matches!(c, MessageContent::ToolResult { .. }))
+        }
+        _ => false,
+    }
+}
+
+fn is_first_turn(chain: &[ChatMessage]) -> bool {
+    !chain
+        .iter()
+        .any(|m| matches!(m.entry, ChatEntry::Completion(_)))
+}
+
+impl ChatState {
+    /// Pick the model for the next completion from the routing rules,
+    /// falling back to `settings.model_config` when no rule matches
+    pub fn route_model(&mut self) -> ModelConfig {
+        if self.settings.routing_rules.is_empty() {
+            return self.settings.model_config.clone();
+        }
+
+        let chain = self.model_chain();
+        let prompt_tokens = estimate_prompt_tokens(&chain, self.settings.system_prompt.as_deref());
+
+        for (i, rule) in self.settings.routing_rules.iter().enumerate() {
+            let matched = rule.conditions.iter().all(|condition| match condition {
+                RouteCondition::PromptTokensAbove { tokens } => prompt_tokens > *tokens,
+                RouteCondition::ToolResultFollowUp => is_tool_result_follow_up(&chain),
+                RouteCondition::FirstTurn => is_first_turn(&chain),
+            });
+
+            if matched {
+                log(&format!(
+                    "Routing rule {} matched, using {}/{}",
+                    i, rule.model_config.provider, rule.model_config.model
+                ));
+                return rule.model_config.clone();
+            }
+        }
+
+        self.settings.model_config.clone()
+    }
+}
@@ -0,0 +1,291 @@
+use serde_json::{Map, Value};
+
+/// Validate a value against the JSON Schema subset used by MCP tool definitions.
+///
+/// Supports `type` (single or list), `enum`, `const`, `properties`, `required`,
+/// `additionalProperties`, `items`, `minItems`/`maxItems`, `minLength`/`maxLength`
+/// and `minimum`/`maximum`. Unknown keywords are ignored. Returns one message per
+/// violation, prefixed with the path of the offending value.
+pub fn validate(schema: &Value, value: &Value) -> Vec<String> {
+    let mut violations = Vec::new();
+    validate_at(schema, value, "$", &mut violations);
+    violations
+}
+
+fn validate_at(schema: &Value, value: &Value, path: &str, violations: &mut Vec<String>) {
+    let schema = match schema {
+        Value::Object(schema) => schema,
+        // `true`, `false` and anything malformed: only `false` rejects
+        Value::Bool(false) => {
+            violations.push(format!("{}: no value is allowed here", path));
+            return;
+        }
+        _ => return,
+    };
+
+    if let Some(expected) = schema.get("type") {
+        let types: Vec<&str> = match expected {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+            _ => vec![],
+        };
+        if !types.is_empty() && !types.iter().any(|t| matches_type(t, value)) {
+            violations.push(format!(
+                "{}: expected {}, got {}",
+                path,
+                types.join(" or "),
+                type_name(value)
+            ));
+            // Nested checks would only repeat the type mismatch
+            return;
+        }
+    }
+
+    if let Some(Value::Array(allowed)) = schema.get("enum") {
+        if !allowed.contains(value) {
+            violations.push(format!(
+                "{}: {} is not one of {}",
+                path,
+                value,
+                Value::Array(allowed.clone())
+            ));
+        }
+    }
+
+    if let Some(expected) = schema.get("const") {
+        if expected != value {
+            violations.push(format!("{}: expected {}, got {}", path, expected, value));
+        }
+    }
+
+    match value {
+        Value::Object(object) => validate_object(schema, object, path, violations),
+        Value::Array(items) => validate_array(schema, items, path, violations),
+        Value::String(s) => {
+            let length = s.chars().count() as u64;
+            if let Some(min) = schema.get("minLength").and_then(Value::as_u64) {
+                if length < min {
+                    violations.push(format!("{}: shorter than {} characters", path, min));
+                }
+            }
+            if let Some(max) = schema.get("maxLength").and_then(Value::as_u64) {
+                if length > max {
+                    violations.push(format!("{}: longer than {} characters", path, max));
+                }
+            }
+        }
+        Value::Number(n) => {
+            let n = n.as_f64().unwrap_or_default();
+            if let Some(min) = schema.get("minimum").and_then(Value::as_f64) {
+                if n < min {
+                    violations.push(format!("{}: {} is less than minimum {}", path, n, min));
+                }
+            }
+            if let Some(max) = schema.get("maximum").and_then(Value::as_f64) {
+                if n > max {
+                    violations.push(format!("{}: {} is greater than maximum {}", path, n, max));
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn validate_object(
+    schema: &Map<String, Value>,
+    object: &Map<String, Value>,
+    path: &str,
+    violations: &mut Vec<String>,
+) {
+    if let Some(Value::Array(required)) = schema.get("required") {
+        for key in required.iter().filter_map(Value::as_str) {
+            if !object.contains_key(key) {
+                violations.push(format!("{}: missing required property '{}'", path, key));
+            }
+        }
+    }
+
+    let properties = schema.get("properties").and_then(Value::as_object);
+    for (key, property_value) in object {
+        let property_path = format!("{}.{}", path, key);
+        match properties.and_then(|p| p.get(key)) {
+            Some(property_schema) => {
+                validate_at(property_schema, property_value, &property_path, violations)
+            }
+            None => match schema.get("additionalProperties") {
+                Some(Value::Bool(false)) => {
+                    violations.push(format!("{}: unexpected property '{}'", path, key));
+                }
+                Some(additional) => {
+                    validate_at(additional, property_value, &property_path, violations)
+                }
+                None => {}
+            },
+        }
+    }
+}
+
+fn validate_array(
+    schema: &Map<String, Value>,
+    items: &[Value],
+    path: &str,
+    violations: &mut Vec<String>,
+) {
+    if let Some(min) = schema.get("minItems").and_then(Value::as_u64) {
+        if (items.len() as u64) < min {
+            violations.push(format!("{}: fewer than {} items", path, min));
+        }
+    }
+    if let Some(max) = schema.get("maxItems").and_then(Value::as_u64) {
+        if (items.len() as u64) > max {
+            violations.push(format!("{}: more than {} items", path, max));
+        }
+    }
+
+    if let Some(item_schema) = schema.get("items") {
+        for (i, item) in items.iter().enumerate() {
+            validate_at(item_schema, item, &format!("{}[{}]", path, i), violations);
+        }
+    }
+}
+
+fn matches_type(expected: &str, value: &Value) -> bool {
+    match expected {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        "number" => value.is_number(),
+        "integer" => match value {
+            Value::Number(n) => {
+                n.is_i64() || n.is_u64() || n.as_f64().is_some_and(|f| f.fract() == 0.0)
+            }
+            _ => false,
+        },
+        // Unknown type names are not ours to reject
+        _ => true,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::validate;
+    use serde_json::json;
+
+    #[test]
+    fn accepts_matching_types() {
+        assert!(validate(&json!({"type": "string"}), &json!("a")).is_empty());
+        assert!(validate(&json!({"type": "integer"}), &json!(3)).is_empty());
+        assert!(validate(&json!({"type": "integer"}), &json!(3.0)).is_empty());
+        assert!(validate(&json!({"type": ["string", "null"]}), &json!(null)).is_empty());
+    }
+
+    #[test]
+    fn rejects_mismatched_types() {
+        assert_eq!(
+            validate(&json!({"type": "string"}), &json!(1)),
+            vec!["$: expected string, got number"]
+        );
+        assert_eq!(
+            validate(&json!({"type": "integer"}), &json!(1.5)),
+            vec!["$: expected integer, got number"]
+        );
+        assert_eq!(
+            validate(&json!({"type": ["string", "null"]}), &json!(true)),
+            vec!["$: expected string or null, got boolean"]
+        );
+    }
+
+    #[test]
+    fn reports_missing_required_properties() {
+        let schema = json!({
+            "type": "object",
+            "properties": {"path": {"type": "string"}},
+            "required": ["path", "mode"]
+        });
+        assert_eq!(
+            validate(&schema, &json!({"path": "a"})),
+            vec!["$: missing required property 'mode'"]
+        );
+    }
+
+    #[test]
+    fn checks_enum_and_const() {
+        let schema = json!({"enum": ["read", "write"]});
+        assert!(validate(&schema, &json!("read")).is_empty());
+        assert_eq!(
+            validate(&schema, &json!("delete")),
+            vec![r#"$: "delete" is not one of ["read","write"]"#]
+        );
+        assert_eq!(
+            validate(&json!({"const": 1}), &json!(2)),
+            vec!["$: expected 1, got 2"]
+        );
+    }
+
+    #[test]
+    fn reports_nested_object_paths() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "options": {
+                    "type": "object",
+                    "properties": {"depth": {"type": "integer", "minimum": 1}},
+                    "additionalProperties": false
+                }
+            }
+        });
+        let value = json!({"options": {"depth": 0, "extra": true}});
+        let mut violations = validate(&schema, &value);
+        violations.sort();
+        assert_eq!(
+            violations,
+            vec![
+                "$.options.depth: 0 is less than minimum 1",
+                "$.options: unexpected property 'extra'",
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_array_items_and_bounds() {
+        let schema = json!({
+            "type": "array",
+            "items": {"type": "string", "maxLength": 3},
+            "minItems": 1,
+            "maxItems": 2
+        });
+        assert!(validate(&schema, &json!(["a", "bc"])).is_empty());
+        assert_eq!(validate(&schema, &json!([])), vec!["$: fewer than 1 items"]);
+        assert_eq!(
+            validate(&schema, &json!(["a", 2, "long"])),
+            vec![
+                "$: more than 2 items",
+                "$[1]: expected string, got number",
+                "$[2]: longer than 3 characters",
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_unknown_keywords_and_permissive_schemas() {
+        assert!(validate(&json!({"format": "uri"}), &json!("x")).is_empty());
+        assert!(validate(&json!(true), &json!({"any": 1})).is_empty());
+        assert_eq!(
+            validate(&json!(false), &json!(1)),
+            vec!["$: no value is allowed here"]
+        );
+    }
+}
@@ -0,0 +1,193 @@
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store::{self, ContentRef};
+use crate::bindings::theater::simple::timing;
+use crate::state::{ChatState, ConversationSettings};
+use serde::{Deserialize, Serialize};
+use serde_json::{to_vec, Value};
+
+/// One entry in a conversation's settings history
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SettingsVersion {
+    /// Hash of the stored settings blob, which identifies the version
+    pub version: String,
+
+    /// `timing::now()` when the version became current
+    pub timestamp: u64,
+
+    /// Top-level settings fields that differ from the previous version
+    pub changed: Vec<String>,
+
+    /// Previous entry in the history
+    pub previous: Option<String>,
+}
+
+/// Top-level fields whose values differ between two settings objects
+fn changed_fields(before: &Value, after: &Value) -> Vec<String> {
+    let (before, after) = match (before.as_object(), after.as_object()) {
+        (Some(before), Some(after)) => (before, after),
+        _ => return Vec::new(),
+    };
+
+    let mut changed: Vec<String> = before
+        .keys()
+        .chain(after.keys())
+        .filter(|key| before.get(*key) != after.get(*key))
+        .cloned()
+        .collect();
+    changed.sort();
+    changed.dedup();
+    changed
+}
+
+impl ChatState {
+    fn settings_history_label(&self) -> String {
+        format!("settings_history_{}", self.conversation_id)
+    }
+
+    /// Settings without the runtime state of MCP servers, so restarts don't
+    /// produce new versions
+    fn settings_snapshot(&self) -> ConversationSettings {
+        let mut snapshot = self.settings.clone();
+        for mcp in &mut snapshot.mcp_servers {
+            mcp.actor_id = None;
+            mcp.tools = None;
+            mcp.restart_count = 0;
+        }
+        snapshot
+    }
+
+    fn load_settings_version(&self, entry_ref: &ContentRef) -> Result<SettingsVersion, String> {
+        let entry_bytes = store::get(&self.store_id, entry_ref)
+            .map_err(|e| format!("Failed to read settings version: {}", e))?;
+        serde_json::from_slice(&entry_bytes)
+            .map_err(|e| format!("Failed to parse settings version: {}", e))
+    }
+
+    fn load_settings_blob(&self, version: &str) -> Result<Value, String> {
+        let settings_ref = ContentRef {
+            hash: version.to_string(),
+        };
+        let settings_bytes = store::get(&self.store_id, &settings_ref)
+            .map_err(|e| format!("Failed to read settings version {}: {}", version, e))?;
+        serde_json::from_slice(&settings_bytes)
+            .map_err(|e| format!("Failed to parse settings version {}: {}", version, e))
+    }
+
+    /// Store the current settings as a new version, unless they match the latest one
+    pub fn record_settings_version(&mut self) -> Result<(), String> {
+        let snapshot = serde_json::to_value(self.settings_snapshot())
+            .map_err(|e| format!("Failed to serialize settings: {}", e))?;
+        let snapshot_bytes =
+            to_vec(&snapshot).map_err(|e| format!("Failed to serialize settings: {}", e))?;
+        let settings_ref = store::store(&self.store_id, &snapshot_bytes)
+            .map_err(|e| format!("Failed to store settings version: {}", e))?;
+
+        let label = self.settings_history_label();
+        let head = store::get_by_label(&self.store_id, &label)
+            .map_err(|e| format!("Failed to read settings history head: {}", e))?;
+
+        let changed = match &head {
+            Some(head_ref) => {
+                let latest = self.load_settings_version(head_ref)?;
+                if latest.version == settings_ref.hash {
+                    self.settings_version = Some(latest.version);
+                    return Ok(());
+                }
+                changed_fields(&self.load_settings_blob(&latest.version)?, &snapshot)
+            }
+            None => Vec::new(),
+        };
+
+        log(&format!(
+            "Recording settings version {} (changed: {:?})",
+            settings_ref.hash, changed
+        ));
+        let entry = SettingsVersion {
+            version: settings_ref.hash.clone(),
+            timestamp: timing::now(),
+            changed,
+            previous: head.as_ref().map(|r| r.hash.clone()),
+        };
+        let entry_bytes =
+            to_vec(&entry).map_err(|e| format!("Failed to serialize settings version: {}", e))?;
+        let entry_ref = store::store(&self.store_id, &entry_bytes)
+            .map_err(|e| format!("Failed to store settings version: {}", e))?;
+
+        let result = if head.is_none() {
+            store::label(&self.store_id, &label, &entry_ref)
+        } else {
+            store::replace_at_label(&self.store_id, &label, &entry_ref)
+        };
+        result.map_err(|e| format!("Failed to update settings history head: {}", e))?;
+
+        self.settings_version = Some(settings_ref.hash);
+        Ok(())
+    }
+
+    /// Every recorded settings version, oldest first
+    pub fn get_settings_history(&self) -> Result<Vec<SettingsVersion>, String> {
+        log("Reading settings history");
+
+        let mut versions = Vec::new();
+        let mut current = store::get_by_label(&self.store_id, &self.settings_history_label())
+            .map_err(|e| format!("Failed to read settings history head: {}", e))?;
+
+        while let Some(entry_ref) = current {
+            let entry = self.load_settings_version(&entry_ref)?;
+            current = entry.previous.clone().map(|hash| ContentRef { hash });
+            versions.push(entry);
+        }
+
+        versions.reverse();
+        Ok(versions)
+    }
+
+    /// Settings as they were at `version`, which must be in this conversation's history
+    pub fn settings_at_version(&self, version: &str) -> Result<ConversationSettings, String> {
+        if !self
+            .get_settings_history()?
+            .iter()
+            .any(|v| v.version == version)
+        {
+            return Err(format!("Settings version {} not found", version));
+        }
+
+        serde_json::from_value(self.load_settings_blob(version)?)
+            .map_err(|e| format!("Failed to parse settings version {}: {}", version, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::changed_fields;
+    use serde_json::json;
+
+    #[test]
+    fn lists_changed_added_and_removed_fields_sorted() {
+        assert_eq!(
+            changed_fields(
+                &json!({"title": "a", "temperature": 0.5, "max_tokens": 100}),
+                &json!({"title": "b", "max_tokens": 100, "system_prompt": "hi"})
+            ),
+            vec!["system_prompt", "temperature", "title"]
+        );
+    }
+
+    #[test]
+    fn compares_nested_values_as_one_field() {
+        assert_eq!(
+            changed_fields(
+                &json!({"model_config": {"model": "a", "provider": "p"}}),
+                &json!({"model_config": {"model": "b", "provider": "p"}})
+            ),
+            vec!["model_config"]
+        );
+    }
+
+    #[test]
+    fn reports_nothing_for_equal_or_non_object_settings() {
+        let settings = json!({"title": "a", "mcp_servers": []});
+        assert!(changed_fields(&settings, &settings).is_empty());
+        assert!(changed_fields(&json!(null), &settings).is_empty());
+    }
+}
@@ -2,20 +2,32 @@ use crate::bindings::theater::simple::message_server_host;
 use crate::bindings::theater::simple::message_server_host::respond_to_request;
 use crate::bindings::theater::simple::runtime::log;
 use crate::bindings::theater::simple::store::{self, ContentRef};
-use crate::bindings::theater::simple::supervisor::spawn;
+use crate::bindings::theater::simple::supervisor::{restart_child, spawn, stop_child};
+use crate::bindings::theater::simple::timing;
+use crate::audit::ToolAuditEntry;
+use crate::cache::CachedToolResult;
+use crate::catalog::CachedModels;
+use crate::completion::{default_retryable_errors, CompletionFailure};
+use crate::moderation::{load_moderation, Moderation};
+use crate::params::CompletionParams;
 use crate::protocol::{ChatStateRequest, ChatStateResponse, McpActorRequest, McpResponse};
-use crate::proxy::Proxy;
+use crate::proxy::{default_providers, ProviderConfig, ProviderStatus, Proxy};
+use crate::routing::RoutingRule;
+use crate::schema;
 use crate::state::message_server_host::send;
+use crate::tools;
+use crate::undo::HeadHistory;
+use crate::validation::{apply_settings, SettingsError};
 use crate::MCP_POC_MANIFEST;
 use genai_types::messages::Role;
 use genai_types::{
     messages::StopReason, CompletionRequest, CompletionResponse, Message, MessageContent,
-    ModelInfo, ProxyRequest, ProxyResponse,
+    ProxyRequest, ProxyResponse,
 };
 use mcp_protocol::tool::{Tool, ToolCallResult, ToolContent};
 use serde::{Deserialize, Serialize};
 use serde_json::{to_vec, Value};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Display;
 use thiserror::Error;
 
@@ -47,6 +59,34 @@ pub struct ChatState {
 
     /// Pending completion request id
     pub pending_completion: Option<String>,
+
+    /// Content queued to be attached to the next user message
+    #[serde(default)]
+    pub pending_attachments: Vec<MessageContent>,
+
+    /// Cached results of deterministic tool calls
+    #[serde(default)]
+    pub tool_cache: HashMap<String, CachedToolResult>,
+
+    /// References of oversized tool results stored by this conversation
+    #[serde(default)]
+    pub stored_tool_results: HashSet<String>,
+
+    /// Models listed by each provider
+    #[serde(default)]
+    pub model_catalog: HashMap<String, CachedModels>,
+
+    /// Settings version currently in effect
+    #[serde(default)]
+    pub settings_version: Option<String>,
+
+    /// Previous heads for undo and redo
+    #[serde(default)]
+    pub head_history: HeadHistory,
+
+    /// Hidden and redacted messages
+    #[serde(default)]
+    pub moderation: Moderation,
 }
 
 #[derive(Error, Debug, Clone, Serialize, Deserialize)]
@@ -66,6 +106,45 @@ pub struct ChatMessage {
     pub id: Option<String>,
     pub parent_id: Option<String>,
     pub entry: ChatEntry,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<MessageMetadata>,
+}
+
+/// Information about how a message was produced
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct MessageMetadata {
+    /// Tool use ids whose results were served from the tool cache
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cached_tool_results: Vec<String>,
+
+    /// Model that produced a completion
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub answered_by: Option<ModelConfig>,
+
+    /// Errors from attempts that failed before the completion succeeded
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failover_errors: Vec<String>,
+
+    /// Settings version in effect when a completion was generated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub settings_version: Option<String>,
+}
+
+/// Results of the tool calls requested by one completion
+#[derive(Debug, Default)]
+pub struct ProcessedTools {
+    /// Tool results to send back to the model
+    pub content: Vec<MessageContent>,
+    pub metadata: MessageMetadata,
+}
+
+impl MessageMetadata {
+    pub fn is_empty(&self) -> bool {
+        self.cached_tool_results.is_empty()
+            && self.answered_by.is_none()
+            && self.failover_errors.is_empty()
+            && self.settings_version.is_none()
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -73,6 +152,16 @@ pub enum ChatEntry {
     Message(Message),
     Completion(CompletionResponse),
     Error(ChatError),
+    Local(LocalEntry),
+}
+
+/// A slash command and chat-state's reply to it. Never sent to the model.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LocalEntry {
+    pub command: String,
+    pub output: String,
+    #[serde(default)]
+    pub is_error: bool,
 }
 
 impl From<ChatEntry> for Message {
@@ -84,11 +173,17 @@ impl From<ChatEntry> for Message {
                 role: Role::User,
                 content: vec![MessageContent::Text { text: err.message }],
             },
+            ChatEntry::Local(local) => Message {
+                role: Role::Assistant,
+                content: vec![MessageContent::Text {
+                    text: local.output,
+                }],
+            },
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct ModelConfig {
     pub model: String,
     pub provider: String,
@@ -114,6 +209,66 @@ pub struct InitConversationSettings {
 
     /// Mcp servers
     pub mcp_servers: Option<Vec<McpServer>>,
+
+    /// Maximum size in bytes of a single tool result sent inline to the model
+    #[serde(default)]
+    pub tool_result_limit: Option<usize>,
+
+    /// Expose the built-in conversation history tools to the model
+    #[serde(default)]
+    pub introspection_tools: bool,
+
+    /// Note the model can read with the built-in read_note tool
+    #[serde(default)]
+    pub pinned_note: Option<String>,
+
+    /// Model providers to spawn proxies for
+    #[serde(default)]
+    pub providers: Option<Vec<ProviderConfig>>,
+
+    /// Stop proxies unused for this many milliseconds
+    #[serde(default)]
+    pub proxy_idle_timeout_ms: Option<u64>,
+
+    /// Models to try, in order, when the primary model keeps failing
+    #[serde(default)]
+    pub fallback_models: Option<Vec<ModelConfig>>,
+
+    /// Retry policy for transient completion failures
+    #[serde(default)]
+    pub completion_retry: Option<RetryPolicy>,
+
+    /// Provider error substrings that count as transient
+    #[serde(default)]
+    pub retryable_errors: Option<Vec<String>>,
+
+    /// Rules choosing a model per completion
+    #[serde(default)]
+    pub routing_rules: Option<Vec<RoutingRule>>,
+
+    /// Values for `{{name}}` placeholders in the system prompt
+    #[serde(default)]
+    pub prompt_variables: Option<HashMap<String, String>>,
+
+    /// Completion parameters for every provider
+    #[serde(default)]
+    pub completion_params: Option<CompletionParams>,
+
+    /// Completion parameters per provider, layered over `completion_params`
+    #[serde(default)]
+    pub provider_params: Option<HashMap<String, CompletionParams>>,
+
+    /// Generate a title after the first exchange
+    #[serde(default)]
+    pub auto_title: Option<bool>,
+
+    /// Model used to generate titles
+    #[serde(default)]
+    pub title_model: Option<ModelConfig>,
+
+    /// Handle user messages starting with `/` as commands
+    #[serde(default)]
+    pub slash_commands: bool,
 }
 
 /// Into ConversationSettings trait to convert InitConversationSettings to ConversationSettings
@@ -124,8 +279,27 @@ impl From<InitConversationSettings> for ConversationSettings {
             temperature: init.temperature,
             max_tokens: init.max_tokens,
             system_prompt: init.system_prompt,
+            // Init requires a title, so only a non-default one counts as set by hand
+            title_settled: init.title != DEFAULT_TITLE,
             title: init.title,
             mcp_servers: init.mcp_servers.unwrap_or_default(),
+            tool_result_limit: init.tool_result_limit.or_else(default_tool_result_limit),
+            introspection_tools: init.introspection_tools,
+            pinned_note: init.pinned_note,
+            providers: init.providers.unwrap_or_else(default_providers),
+            proxy_idle_timeout_ms: init.proxy_idle_timeout_ms,
+            fallback_models: init.fallback_models.unwrap_or_default(),
+            completion_retry: init.completion_retry.unwrap_or_default(),
+            retryable_errors: init
+                .retryable_errors
+                .unwrap_or_else(default_retryable_errors),
+            routing_rules: init.routing_rules.unwrap_or_default(),
+            prompt_variables: init.prompt_variables.unwrap_or_default(),
+            completion_params: init.completion_params.unwrap_or_default(),
+            provider_params: init.provider_params.unwrap_or_default(),
+            auto_title: init.auto_title.unwrap_or(true),
+            title_model: init.title_model,
+            slash_commands: init.slash_commands,
         }
     }
 }
@@ -150,8 +324,92 @@ pub struct ConversationSettings {
 
     /// Mcp servers
     pub mcp_servers: Vec<McpServer>,
+
+    /// Maximum size in bytes of a single tool result sent inline to the model.
+    /// Larger results are stored separately and replaced with a preview.
+    #[serde(default = "default_tool_result_limit")]
+    pub tool_result_limit: Option<usize>,
+
+    /// Expose the built-in conversation history tools to the model
+    #[serde(default)]
+    pub introspection_tools: bool,
+
+    /// Note the model can read with the built-in read_note tool
+    #[serde(default)]
+    pub pinned_note: Option<String>,
+
+    /// Model providers to spawn proxies for
+    #[serde(default = "default_providers")]
+    pub providers: Vec<ProviderConfig>,
+
+    /// Stop proxies unused for this many milliseconds. They are spawned again on next use.
+    #[serde(default)]
+    pub proxy_idle_timeout_ms: Option<u64>,
+
+    /// Models to try, in order, when the primary model keeps failing
+    #[serde(default)]
+    pub fallback_models: Vec<ModelConfig>,
+
+    /// Retry policy for transient completion failures, applied to each model
+    #[serde(default)]
+    pub completion_retry: RetryPolicy,
+
+    /// Provider error substrings (case-insensitive) that count as transient.
+    /// Failures to reach the proxy are always transient.
+    #[serde(default = "default_retryable_errors")]
+    pub retryable_errors: Vec<String>,
+
+    /// Rules choosing a model per completion. The first rule whose conditions
+    /// all match wins; otherwise `model_config` is used.
+    #[serde(default)]
+    pub routing_rules: Vec<RoutingRule>,
+
+    /// Values for `{{name}}` placeholders in the system prompt. The built-in
+    /// `date`, `title`, `model` and `tools` variables take precedence.
+    #[serde(default)]
+    pub prompt_variables: HashMap<String, String>,
+
+    /// Completion parameters for every provider. See `params::supported_params`
+    /// for which providers accept which parameters.
+    #[serde(default)]
+    pub completion_params: CompletionParams,
+
+    /// Completion parameters per provider name, layered over `completion_params`
+    #[serde(default)]
+    pub provider_params: HashMap<String, CompletionParams>,
+
+    /// Generate a title after the first exchange unless `title_settled`
+    #[serde(default = "default_auto_title")]
+    pub auto_title: bool,
+
+    /// Set once a title was generated or attempted, or the title was set by
+    /// hand. Automatic titles only run while this is false.
+    #[serde(default)]
+    pub title_settled: bool,
+
+    /// Model used to generate titles, usually a cheap one. Defaults to `model_config`.
+    #[serde(default)]
+    pub title_model: Option<ModelConfig>,
+
+    /// Handle user messages starting with `/` locally instead of storing them.
+    /// See `commands::COMMANDS`.
+    #[serde(default)]
+    pub slash_commands: bool,
+}
+
+fn default_tool_result_limit() -> Option<usize> {
+    Some(DEFAULT_TOOL_RESULT_LIMIT)
+}
+
+fn default_auto_title() -> bool {
+    true
 }
 
+/// Title of a conversation that has not been named yet
+pub const DEFAULT_TITLE: &str = "title";
+const DEFAULT_TOOL_RESULT_LIMIT: usize = 64 * 1024;
+const DEFAULT_PROXY_IDLE_TIMEOUT_MS: u64 = 10 * 60 * 1000;
+
 impl Default for ConversationSettings {
     fn default() -> Self {
         ConversationSettings {
@@ -162,25 +420,41 @@ impl Default for ConversationSettings {
             temperature: None,
             max_tokens: 65535,
             system_prompt: None,
-            title: "title".to_string(),
+            title: DEFAULT_TITLE.to_string(),
             mcp_servers: vec![],
+            tool_result_limit: default_tool_result_limit(),
+            introspection_tools: false,
+            pinned_note: None,
+            providers: default_providers(),
+            proxy_idle_timeout_ms: Some(DEFAULT_PROXY_IDLE_TIMEOUT_MS),
+            fallback_models: vec![],
+            completion_retry: RetryPolicy::default(),
+            retryable_errors: default_retryable_errors(),
+            routing_rules: vec![],
+            prompt_variables: HashMap::new(),
+            completion_params: CompletionParams::default(),
+            provider_params: HashMap::new(),
+            auto_title: true,
+            title_settled: false,
+            title_model: None,
+            slash_commands: false,
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct StdPipeMcpConfig {
     command: String,
     args: Vec<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct ActorMcpConfig {
     manifest_path: String,
     init_state: Option<Value>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum McpConfig {
     #[serde(rename = "stdio")]
     StdPipe(StdPipeMcpConfig),
@@ -188,15 +462,252 @@ pub enum McpConfig {
     Actor(ActorMcpConfig),
 }
 
+impl McpConfig {
+    /// Fields that would keep the server from starting, with the reason
+    pub fn problems(&self) -> Vec<(&'static str, String)> {
+        let mut problems = Vec::new();
+        match self {
+            McpConfig::StdPipe(config) => {
+                if config.command.trim().is_empty() {
+                    problems.push(("command", "must not be empty".to_string()));
+                }
+            }
+            McpConfig::Actor(config) => {
+                if config.manifest_path.trim().is_empty() {
+                    problems.push(("manifest_path", "must not be empty".to_string()));
+                }
+            }
+        }
+        problems
+    }
+}
+
+/// Retry behavior for calls that fail with a transient error
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RetryPolicy {
+    /// Number of retries after the first attempt
+    pub max_retries: u32,
+
+    /// Delay before the first retry, in milliseconds
+    pub initial_backoff_ms: u64,
+
+    /// Upper bound for the delay between retries, in milliseconds
+    pub max_backoff_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 2,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 5000,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before the given retry (1-based), doubling up to `max_backoff_ms`
+    pub fn backoff_for(&self, retry: u32) -> u64 {
+        let factor = 1u64 << retry.saturating_sub(1).min(16);
+        self.initial_backoff_ms
+            .saturating_mul(factor)
+            .min(self.max_backoff_ms)
+    }
+}
+
+const DEFAULT_TOOL_TIMEOUT_MS: u64 = 60_000;
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct McpServer {
+    /// Name used to match this server across settings updates
+    #[serde(default)]
+    pub name: Option<String>,
+
     pub actor_id: Option<String>,
     #[serde(flatten)]
     pub config: McpConfig,
     pub tools: Option<Vec<Tool>>,
+
+    /// Time after which a call to any tool on this server is reported as
+    /// late, in milliseconds. The host request cannot be interrupted, so a
+    /// late reply is still returned.
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+
+    /// Per-tool timeouts, overriding `timeout_ms`
+    #[serde(default)]
+    pub tool_timeouts: Option<HashMap<String, u64>>,
+
+    /// Retry policy for requests that could not be delivered. No retries when unset.
+    #[serde(default)]
+    pub retry: Option<RetryPolicy>,
+
+    /// Tools whose results may be reused for identical arguments, with a TTL in milliseconds
+    #[serde(default)]
+    pub cached_tools: Option<HashMap<String, u64>>,
+
+    /// How many times a crashed server is restarted before it is given up on
+    #[serde(default)]
+    pub max_restarts: Option<u32>,
+
+    /// Restarts performed since the server was last started from settings
+    #[serde(default)]
+    pub restart_count: u32,
 }
 
+const DEFAULT_MAX_RESTARTS: u32 = 3;
+
 impl McpServer {
+    /// Spawn the MCP actor and fetch its tool list
+    pub fn start(&mut self) -> Result<(), String> {
+        let actor_id = match &self.config {
+            McpConfig::StdPipe(config) => {
+                log(&format!(
+                    "Starting MCP server with stdio: {} {:?}",
+                    config.command, config.args
+                ));
+                let config_bytes = serde_json::to_vec(&config)
+                    .map_err(|e| format!("Failed to serialize stdio config: {}", e))?;
+                spawn(MCP_POC_MANIFEST, Some(&config_bytes))
+            }
+            McpConfig::Actor(config) => {
+                log(&format!(
+                    "Starting MCP server with actor manifest: {}",
+                    config.manifest_path
+                ));
+                let init_state_bytes = serde_json::to_vec(&config.init_state)
+                    .map_err(|e| format!("Failed to serialize init state: {}", e))?;
+                spawn(&config.manifest_path, Some(&init_state_bytes))
+            }
+        };
+
+        match actor_id {
+            Ok(id) => {
+                log(&format!("MCP server started with actor ID: {}", id));
+                self.actor_id = Some(id);
+                self.fetch_tools()
+            }
+            Err(e) => {
+                log(&format!("Failed to start MCP server: {}", e));
+                Err(format!("Failed to start MCP server: {}", e))
+            }
+        }
+    }
+
+    /// Ask the running MCP actor for its tools and cache them
+    pub fn fetch_tools(&mut self) -> Result<(), String> {
+        let id = self.actor_id.as_ref()
+            .ok_or("MCP server not started")?;
+
+        // Send the actor a list tools request
+        let tool_list_request = McpActorRequest::ToolsList {};
+        let request_bytes = to_vec(&tool_list_request)
+            .map_err(|e| format!("Failed to serialize tool list request: {}", e))?;
+        
+        log(&format!("Sending tool list request to MCP server: {}", id));
+        
+        let response_bytes = message_server_host::request(id, &request_bytes)
+            .map_err(|e| format!("Failed to send tool list request to MCP server: {}", e))?;
+
+        let response: McpResponse = serde_json::from_slice(&response_bytes)
+            .map_err(|e| format!("Failed to parse tool list response: {}", e))?;
+
+        if let Some(result) = response.result {
+            let tools_value = result.get("tools")
+                .ok_or("No 'tools' field in MCP response")?;
+            
+            let tools = serde_json::from_value::<Vec<Tool>>(tools_value.clone())
+                .map_err(|e| format!("Failed to parse tool list from response: {}", e))?;
+
+            self.tools = Some(tools);
+            Ok(())
+        } else if let Some(error) = response.error {
+            log(&format!("Error in tool list response: {:?}", error));
+            Err(format!("Error in tool list response: {}", error.message))
+        } else {
+            log("No result or error in tool list response");
+            Err("No result or error in tool list response".to_string())
+        }
+    }
+
+    /// Send a request to the MCP actor and return the JSON-RPC result
+    pub fn request(&self, request: &McpActorRequest) -> Result<Value, String> {
+        let actor_id = self.actor_id.as_ref()
+            .ok_or("MCP server not started")?;
+
+        let request_bytes = to_vec(request)
+            .map_err(|e| format!("Failed to serialize MCP request: {}", e))?;
+
+        let response_bytes = message_server_host::request(actor_id, &request_bytes)
+            .map_err(|e| format!("Failed to send request to MCP server: {}", e))?;
+
+        let response: McpResponse = serde_json::from_slice(&response_bytes)
+            .map_err(|e| format!("Failed to parse MCP response: {}", e))?;
+
+        match (response.result, response.error) {
+            (_, Some(error)) => Err(format!("MCP server error: {}", error.message)),
+            (Some(result), None) => Ok(result),
+            (None, None) => Err("No result or error in MCP response".to_string()),
+        }
+    }
+
+    /// Name used to refer to this server in requests, falling back to its actor id
+    pub fn label(&self) -> Option<String> {
+        self.name.clone().or_else(|| self.actor_id.clone())
+    }
+
+    /// Stop the MCP actor if it is running
+    pub fn stop(&mut self) {
+        if let Some(actor_id) = self.actor_id.take() {
+            log(&format!("Stopping MCP server with actor ID: {}", actor_id));
+            if let Err(e) = stop_child(&actor_id) {
+                log(&format!("Failed to stop MCP server {}: {}", actor_id, e));
+            }
+        }
+        self.tools = None;
+    }
+
+    /// Restart a crashed MCP actor, giving up once `max_restarts` is exhausted
+    pub fn restart(&mut self) -> Result<(), String> {
+        let actor_id = self.actor_id.clone()
+            .ok_or("MCP server not started")?;
+        let max_restarts = self.max_restarts.unwrap_or(DEFAULT_MAX_RESTARTS);
+
+        if self.restart_count >= max_restarts {
+            log(&format!(
+                "MCP server {} exceeded {} restarts, stopping it",
+                actor_id, max_restarts
+            ));
+            self.stop();
+            return Err(format!(
+                "MCP server {} exceeded {} restarts",
+                actor_id, max_restarts
+            ));
+        }
+
+        self.restart_count += 1;
+        log(&format!(
+            "Restarting MCP server {} (restart {}/{})",
+            actor_id, self.restart_count, max_restarts
+        ));
+
+        if let Err(e) = restart_child(&actor_id) {
+            log(&format!("Failed to restart MCP server {}: {}", actor_id, e));
+            self.stop();
+            return Err(format!("Failed to restart MCP server {}: {}", actor_id, e));
+        }
+
+        self.fetch_tools()
+    }
+
+    /// Whether `other` describes the same server, by name if set, otherwise by config
+    pub fn matches(&self, other: &McpServer) -> bool {
+        match (&self.name, &other.name) {
+            (Some(a), Some(b)) => a == b,
+            _ => self.config == other.config,
+        }
+    }
+
     pub fn call_tool(&self, tool: String, args: Value) -> Result<McpResponse, String> {
         log(&format!("Calling tool: {} with args: {:?}", tool, args));
         // Check if the MCP server is started
@@ -213,22 +724,97 @@ impl McpServer {
         let tools = self.tools.as_ref()
             .ok_or("No tools available")?;
         
-        if !tools.iter().any(|t| t.name == tool) {
-            return Err(format!("Tool {} not found", tool));
+        let definition = tools.iter().find(|t| t.name == tool)
+            .ok_or_else(|| format!("Tool {} not found", tool))?;
+
+        // Reject arguments the MCP server would not accept
+        let violations = schema::validate(&definition.input_schema, &args);
+        if !violations.is_empty() {
+            log(&format!("Invalid arguments for tool {}: {:?}", tool, violations));
+            return Err(format!(
+                "Invalid arguments for tool {}:\n- {}",
+                tool,
+                violations.join("\n- ")
+            ));
         }
 
         // Call the tool with the given arguments
         let actor_id = self.actor_id.as_ref()
             .ok_or("MCP server not started")?;
         
-        let request_bytes = to_vec(&McpActorRequest::ToolsCall { name: tool, args })
+        let timeout_ms = self.timeout_for(&tool);
+
+        let request_bytes = to_vec(&McpActorRequest::ToolsCall { name: tool.clone(), args })
             .map_err(|e| format!("Failed to serialize tool use request: {}", e))?;
-        
-        let result = message_server_host::request(actor_id, &request_bytes)
+
+        // Retries are opt-in and only cover requests that never reached the
+        // server, so a non-idempotent tool is never run twice
+        let mut attempt = 0;
+        loop {
+            match Self::request_with_deadline(actor_id, &tool, &request_bytes, timeout_ms) {
+                Ok(result) => {
+                    return serde_json::from_slice(&result)
+                        .map_err(|e| format!("Failed to parse tool response: {}", e));
+                }
+                Err(e) => match &self.retry {
+                    Some(retry) if attempt < retry.max_retries => {
+                        attempt += 1;
+                        let backoff = retry.backoff_for(attempt);
+                        log(&format!(
+                            "Tool {} failed ({}), retrying in {} ms (retry {}/{})",
+                            tool, e, backoff, attempt, retry.max_retries
+                        ));
+                        if let Err(e) = timing::sleep(backoff) {
+                            log(&format!("Failed to sleep before retry: {}", e));
+                        }
+                    }
+                    _ => {
+                        return Err(format!(
+                            "Tool {} failed after {} attempt(s): {}",
+                            tool,
+                            attempt + 1,
+                            e
+                        ));
+                    }
+                },
+            }
+        }
+    }
+
+    /// Send a request to the MCP actor, logging replies that miss the deadline.
+    ///
+    /// The host request blocks until the actor replies, and `cancel_request`
+    /// only covers requests this actor received, so a server that never
+    /// replies still blocks the call. A late reply means the tool ran, so it is
+    /// returned rather than reported to the model as a failure.
+    fn request_with_deadline(
+        actor_id: &str,
+        tool: &str,
+        request_bytes: &[u8],
+        timeout_ms: u64,
+    ) -> Result<Vec<u8>, String> {
+        let started = timing::now();
+        let result = message_server_host::request(actor_id, request_bytes)
             .map_err(|e| format!("Failed to call tool: {}", e))?;
 
-        serde_json::from_slice(&result)
-            .map_err(|e| format!("Failed to parse tool response: {}", e))
+        let elapsed_ms = timing::now().saturating_sub(started);
+        if elapsed_ms > timeout_ms {
+            log(&format!(
+                "Tool {} replied after {} ms, past its {} ms deadline",
+                tool, elapsed_ms, timeout_ms
+            ));
+        }
+
+        Ok(result)
+    }
+
+    /// Timeout for the given tool, falling back to the server-wide and default values
+    pub fn timeout_for(&self, tool: &str) -> u64 {
+        self.tool_timeouts
+            .as_ref()
+            .and_then(|timeouts| timeouts.get(tool).copied())
+            .or(self.timeout_ms)
+            .unwrap_or(DEFAULT_TOOL_TIMEOUT_MS)
     }
 
     pub fn has_tool(&self, tool: &str) -> bool {
@@ -295,13 +881,20 @@ impl ChatState {
             messages: HashMap::new(),
             settings: conversation_settings,
             subscription_channels: Vec::new(),
-            store_id,
             head,
             pending_completion: None,
+            pending_attachments: Vec::new(),
+            tool_cache: HashMap::new(),
+            stored_tool_results: HashSet::new(),
+            model_catalog: HashMap::new(),
+            settings_version: None,
+            head_history: HeadHistory::default(),
+            moderation: load_moderation(&store_id, &conversation_id),
+            store_id,
         }
     }
 
-    pub fn store_settings(&self) -> Result<(), String> {
+    pub fn store_settings(&mut self) -> Result<(), String> {
         log("Storing conversation settings");
 
         let settings_bytes = to_vec(&self.settings)
@@ -309,6 +902,7 @@ impl ChatState {
         let settings_label = format!("settings_{}", self.conversation_id);
         store::store_at_label(&self.store_id, &settings_label, &settings_bytes)
             .map_err(|e| format!("Failed to store settings: {}", e))?;
+        self.record_settings_version()?;
 
         log("Stored conversation settings successfully");
         Ok(())
@@ -324,71 +918,206 @@ impl ChatState {
                 continue;
             }
 
-            let actor_id = match &mcp.config {
-                McpConfig::StdPipe(config) => {
-                    log(&format!(
-                        "Starting MCP server with stdio: {} {:?}",
-                        config.command, config.args
-                    ));
-                    let config_bytes = serde_json::to_vec(&config)
-                        .map_err(|e| format!("Failed to serialize stdio config: {}", e))?;
-                    spawn(MCP_POC_MANIFEST, Some(&config_bytes))
-                }
-                McpConfig::Actor(config) => {
-                    log(&format!(
-                        "Starting MCP server with actor manifest: {}",
-                        config.manifest_path
-                    ));
-                    let init_state_bytes = serde_json::to_vec(&config.init_state)
-                        .map_err(|e| format!("Failed to serialize init state: {}", e))?;
-                    spawn(&config.manifest_path, Some(&init_state_bytes))
+            mcp.start()?;
+        }
+
+        Ok(())
+    }
+
+    /// Carry running MCP servers over into `incoming`, stopping the ones that
+    /// were removed or whose config changed. Servers left without an actor id
+    /// are started by `start_mcp_servers`.
+    pub fn reconcile_mcp_servers(&mut self, mut incoming: Vec<McpServer>) -> Vec<McpServer> {
+        let mut running = std::mem::take(&mut self.settings.mcp_servers);
+
+        for mcp in &mut incoming {
+            // Actor ids sent by clients are only trusted if we are running them
+            mcp.actor_id = None;
+            mcp.tools = None;
+            mcp.restart_count = 0;
+
+            if let Some(pos) = running.iter().position(|r| r.matches(mcp)) {
+                let mut previous = running.remove(pos);
+                if previous.config == mcp.config {
+                    mcp.actor_id = previous.actor_id.take();
+                    mcp.tools = previous.tools.take();
+                    mcp.restart_count = previous.restart_count;
+                } else {
+                    log("MCP server config changed, restarting it");
+                    previous.stop();
                 }
-            };
-
-            match actor_id {
-                Ok(id) => {
-                    log(&format!("MCP server started with actor ID: {}", id));
-                    mcp.actor_id = Some(id.clone());
-
-                    // Send the actor a list tools request
-                    let tool_list_request = McpActorRequest::ToolsList {};
-                    let request_bytes = to_vec(&tool_list_request)
-                        .map_err(|e| format!("Failed to serialize tool list request: {}", e))?;
-                    
-                    log(&format!("Sending tool list request to MCP server: {}", id));
-                    
-                    let response_bytes = message_server_host::request(&id, &request_bytes)
-                        .map_err(|e| format!("Failed to send tool list request to MCP server: {}", e))?;
-
-                    let response: McpResponse = serde_json::from_slice(&response_bytes)
-                        .map_err(|e| format!("Failed to parse tool list response: {}", e))?;
-
-                    if let Some(result) = response.result {
-                        let tools_value = result.get("tools")
-                            .ok_or("No 'tools' field in MCP response")?;
-                        
-                        let tools = serde_json::from_value::<Vec<Tool>>(tools_value.clone())
-                            .map_err(|e| format!("Failed to parse tool list from response: {}", e))?;
-
-                        mcp.tools = Some(tools);
-                    } else if let Some(error) = response.error {
-                        log(&format!("Error in tool list response: {:?}", error));
-                        return Err(format!("Error in tool list response: {}", error.message));
-                    } else {
-                        log("No result or error in tool list response");
-                        return Err("No result or error in tool list response".to_string());
-                    }
+            }
+        }
+
+        for mut removed in running {
+            log("MCP server removed from settings, stopping it");
+            removed.stop();
+        }
+
+        incoming
+    }
+
+    /// Re-query every running MCP server for its tools and notify subscribers
+    /// if the combined tool set changed
+    pub fn refresh_tools(&mut self) -> Result<Vec<Tool>, String> {
+        log("Refreshing tools from MCP servers");
+
+        let previous = serde_json::to_value(self.get_tools()?)
+            .map_err(|e| format!("Failed to serialize tools: {}", e))?;
+
+        for mcp in &mut self.settings.mcp_servers {
+            if mcp.actor_id.is_none() {
+                continue;
+            }
+            if let Err(e) = mcp.fetch_tools() {
+                log(&format!("Failed to refresh tools: {}", e));
+            }
+        }
+
+        let tools = self.get_tools()?;
+        let current = serde_json::to_value(&tools)
+            .map_err(|e| format!("Failed to serialize tools: {}", e))?;
+        let tools = tools.unwrap_or_default();
+
+        if previous != current {
+            log("Tool set changed");
+            if let Err(e) = self.store_settings() {
+                log(&format!("Failed to store conversation settings: {}", e));
+            }
+            self.broadcast(&ChatStateResponse::ToolsList {
+                tools: tools.clone(),
+            });
+        }
+
+        Ok(tools)
+    }
+
+    /// Find the MCP server running as the given child actor
+    pub fn mcp_server_for_actor(&mut self, actor_id: &str) -> Option<&mut McpServer> {
+        self.settings
+            .mcp_servers
+            .iter_mut()
+            .find(|mcp| mcp.actor_id.as_deref() == Some(actor_id))
+    }
+
+    /// Restart an MCP server after it crashed or exited. Returns false if the
+    /// child is not one of our MCP servers.
+    pub fn handle_mcp_server_failure(&mut self, actor_id: &str) -> bool {
+        let mcp = match self.mcp_server_for_actor(actor_id) {
+            Some(mcp) => mcp,
+            None => return false,
+        };
+
+        if let Err(e) = mcp.restart() {
+            log(&format!("MCP server {} is unavailable: {}", actor_id, e));
+        }
+
+        if let Err(e) = self.store_settings() {
+            log(&format!("Failed to store conversation settings: {}", e));
+        }
+
+        true
+    }
+
+    /// Track proxies for configured providers and stop the ones no longer configured.
+    /// New proxies are spawned on first use.
+    pub fn sync_proxies(&mut self) -> Result<(), String> {
+        let providers = self.settings.providers.clone();
+        let model_catalog = &mut self.model_catalog;
+
+        self.proxies.retain(|name, proxy| {
+            // A proxy that gave up after too many restarts gets a fresh start
+            match providers
+                .iter()
+                .find(|p| &p.name == name && proxy.matches_config(p) && !proxy.has_failed())
+            {
+                Some(provider) => {
+                    proxy.adopt_config(provider);
+                    true
                 }
-                Err(e) => {
-                    log(&format!("Failed to start MCP server: {}", e));
-                    return Err(format!("Failed to start MCP server: {}", e));
+                None => {
+                    proxy.stop();
+                    model_catalog.remove(name);
+                    false
                 }
             }
+        });
+
+        for provider in &providers {
+            if !self.proxies.contains_key(&provider.name) {
+                self.proxies
+                    .insert(provider.name.clone(), Proxy::new(provider));
+            }
         }
 
         Ok(())
     }
 
+    /// Restart a proxy after it crashed or exited. Returns false if the child
+    /// is not one of our proxies.
+    pub fn handle_proxy_failure(&mut self, actor_id: &str) -> bool {
+        match self
+            .proxies
+            .values_mut()
+            .find(|proxy| proxy.actor_id() == Some(actor_id))
+        {
+            Some(proxy) => {
+                proxy.restart();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Liveness and recent error rate of every provider's proxy
+    pub fn get_provider_status(&self) -> Vec<ProviderStatus> {
+        let mut statuses: Vec<ProviderStatus> =
+            self.proxies.values().map(|proxy| proxy.status()).collect();
+        statuses.sort_by(|a, b| a.name.cmp(&b.name));
+        statuses
+    }
+
+    /// Stop proxies that have not been used within `proxy_idle_timeout_ms`.
+    /// There are no timer callbacks, so this runs whenever the actor handles a request.
+    pub fn stop_idle_proxies(&mut self) {
+        let idle_ms = match self.settings.proxy_idle_timeout_ms {
+            Some(idle_ms) => idle_ms,
+            None => return,
+        };
+
+        let now = timing::now();
+        for proxy in self.proxies.values_mut() {
+            if proxy.is_idle(now, idle_ms) {
+                proxy.stop();
+            }
+        }
+    }
+
+    /// Add a provider, or replace the one with the same name. The new settings
+    /// are validated and applied like any other settings update.
+    pub fn add_provider(&mut self, provider: ProviderConfig) -> Result<(), SettingsError> {
+        log(&format!("Adding provider: {}", provider.name));
+        let mut settings = self.settings.clone();
+        settings.providers.retain(|p| p.name != provider.name);
+        settings.providers.push(provider);
+        apply_settings(self, settings)
+    }
+
+    /// Remove a provider. Fails validation while the model, a fallback, a
+    /// routing rule or the title model still refers to it.
+    pub fn remove_provider(&mut self, name: &str) -> Result<(), SettingsError> {
+        log(&format!("Removing provider: {}", name));
+        if !self.settings.providers.iter().any(|p| p.name == name) {
+            return Err(SettingsError::Provider(format!(
+                "Provider {} not found",
+                name
+            )));
+        }
+        let mut settings = self.settings.clone();
+        settings.providers.retain(|p| p.name != name);
+        apply_settings(self, settings)
+    }
+
     pub fn continue_chain(&mut self) -> Result<(), String> {
         let head_id = self.head.as_ref()
             .ok_or("No head message found - conversation chain is empty")?;
@@ -418,6 +1147,7 @@ impl ChatState {
                         log("Received end turn signal from proxy");
                         self.resolve_pending_completion()
                             .map_err(|e| format!("Failed to resolve pending completion after end turn: {}", e))?;
+                        self.maybe_generate_title();
                         Ok(())
                     }
                     StopReason::MaxTokens => {
@@ -435,15 +1165,16 @@ impl ChatState {
                     StopReason::ToolUse => {
                         log("Received tool use signal from proxy");
 
-                        let tool_responses = self.process_tools(completion)
+                        let processed = self.process_tools(completion)
                             .map_err(|e| format!("Failed to process tools: {}", e))?;
 
                         let tool_msg = ChatEntry::Message(Message {
                             role: Role::User,
-                            content: tool_responses.clone(),
+                            content: processed.content,
                         });
 
-                        self.add_message(tool_msg.clone());
+                        let metadata = Some(processed.metadata).filter(|m| !m.is_empty());
+                        self.add_message_with_metadata(tool_msg.clone(), metadata);
 
                         self.generate_completion()
                             .map_err(|e| format!("Failed to generate completion after tool use: {}", e))?;
@@ -467,6 +1198,12 @@ impl ChatState {
                     .map_err(|e| format!("Failed to resolve pending completion after error: {}", e))?;
                 Ok(())
             }
+            ChatEntry::Local(local) => {
+                log(&format!("Last message is a local command reply: {}", local.command));
+                self.resolve_pending_completion()
+                    .map_err(|e| format!("Failed to resolve pending completion after command: {}", e))?;
+                Ok(())
+            }
         }
     }
 
@@ -503,13 +1240,29 @@ impl ChatState {
             return Err("Cannot generate completion: no messages in conversation".to_string());
         }
 
+        // A slash command was just handled, there is nothing for the model to answer
+        let head_is_local = self
+            .head
+            .as_ref()
+            .and_then(|head| self.messages.get(head))
+            .is_some_and(|m| matches!(m.entry, ChatEntry::Local(_)));
+        if head_is_local {
+            log("Head is a local command reply, skipping completion");
+            return self.resolve_pending_completion();
+        }
+
         // Generate a completion
-        let model_response = self.generate_proxy_completion(&self.settings.model_config.provider.clone())
+        let model_config = self.route_model();
+        let (model_response, metadata) = self
+            .complete_with_failover(model_config)
             .map_err(|e| format!("Failed to generate proxy completion: {}", e))?;
 
         log("Generated completion successfully");
 
-        self.add_message(ChatEntry::Completion(model_response.clone()));
+        self.add_message_with_metadata(
+            ChatEntry::Completion(model_response.clone()),
+            Some(metadata),
+        );
 
         let msg = serde_json::to_vec(&ChatStateRequest::ContinueProcessing)
             .map_err(|e| format!("Failed to serialize continue processing message: {}", e))?;
@@ -524,7 +1277,7 @@ impl ChatState {
     pub fn get_tools(&self) -> Result<Option<Vec<Tool>>, String> {
         log("Getting tools from MCP servers");
 
-        let mut tools = Vec::new();
+        let mut tools = tools::builtin_tools(self);
 
         for mcp in &self.settings.mcp_servers {
             if let Some(ref actor_id) = mcp.actor_id {
@@ -549,51 +1302,57 @@ impl ChatState {
 
     /// Call a tool with the given completion
     pub fn process_tools(
-        &self,
+        &mut self,
         completion: CompletionResponse,
-    ) -> Result<Vec<MessageContent>, String> {
+    ) -> Result<ProcessedTools, String> {
         log("Processing tools");
 
-        let mut tool_results = Vec::new();
+        let mut processed = ProcessedTools::default();
 
         for message_content in completion.content {
             match message_content {
                 MessageContent::ToolUse { id, name, input } => {
                     log(&format!("Calling tool: {} with args: {:?}", name, input));
 
-                    // Call the tool with the given arguments
-                    let result = self.call_tool(name, input)?;
-
-                    log(&format!("Tool result: {:?}", result));
-                    let tool_use_result = match result.error {
-                        Some(err) => {
-                            log(&format!("Error calling tool: {}", err.message));
-                            MessageContent::ToolResult {
-                                tool_use_id: id,
-                                content: vec![ToolContent::Text {
-                                    text: err.message.clone(),
-                                }],
-                                is_error: Some(true),
-                            }
-                        }
-                        None => {
-                            log(&format!("Tool call result: {:?}", result.result));
-
-                            let tool_result_value = result.result
-                                .ok_or("No result field in tool response")?;
+                    let started = timing::now();
+                    let cached = self.cached_tool_result(&name, &input);
+                    let was_cached = cached.is_some();
 
-                            let tool_result = serde_json::from_value::<ToolCallResult>(tool_result_value)
-                                .map_err(|e| format!("Failed to parse tool call result: {}", e))?;
-
-                            MessageContent::ToolResult {
-                                tool_use_id: id,
-                                content: tool_result.content,
-                                is_error: None,
-                            }
+                    let (content, is_error, result_size) = match cached {
+                        Some(content) => {
+                            processed.metadata.cached_tool_results.push(id.clone());
+                            let result_size =
+                                serde_json::to_vec(&content).map(|b| b.len()).unwrap_or(0);
+                            (content, None, result_size)
                         }
+                        None => self.run_tool(&name, &input),
                     };
 
-                    tool_results.push(tool_use_result);
+                    // Written per call, so calls made before a later failure are still recorded
+                    let entry = ToolAuditEntry {
+                        timestamp: started,
+                        tool: name.clone(),
+                        server: self.server_for_tool(&name),
+                        args: input,
+                        result_size,
+                        is_error: is_error == Some(true),
+                        duration_ms: timing::now().saturating_sub(started),
+                        approval: "auto".to_string(),
+                        cached: was_cached,
+                        request_message_id: self.head.clone(),
+                        tool_use_id: id.clone(),
+                        redacted: false,
+                        previous: None,
+                    };
+                    if let Err(e) = self.append_tool_audit(vec![entry]) {
+                        log(&format!("Failed to record tool audit entry: {}", e));
+                    }
+
+                    processed.content.push(MessageContent::ToolResult {
+                        tool_use_id: id,
+                        content,
+                        is_error,
+                    });
                 }
                 _ => {
                     log("No tool use message found");
@@ -601,14 +1360,85 @@ impl ChatState {
             }
         }
 
-        Ok(tool_results)
+        Ok(processed)
+    }
+
+    /// Call a single tool, returning the content for the model, its error flag
+    /// and the size in bytes of the result before truncation
+    fn run_tool(&mut self, name: &str, input: &Value) -> (Vec<ToolContent>, Option<bool>, usize) {
+        let (content, is_error) = match self.call_tool_content(name, input) {
+            Ok(result) => result,
+            Err(e) => {
+                log(&format!("Tool call failed: {}", e));
+                (vec![ToolContent::Text { text: e }], Some(true))
+            }
+        };
+        let result_size = serde_json::to_vec(&content).map(|b| b.len()).unwrap_or(0);
+
+        let content = match self.settings.tool_result_limit {
+            Some(limit) => tools::truncate_tool_content(
+                &self.store_id,
+                content,
+                limit,
+                &mut self.stored_tool_results,
+            ),
+            None => content,
+        };
+
+        if is_error != Some(true) {
+            self.cache_tool_result(name, input, &content);
+        }
+
+        (content, is_error, result_size)
+    }
+
+    /// Call a tool and unpack its result content and error flag
+    fn call_tool_content(
+        &mut self,
+        name: &str,
+        input: &Value,
+    ) -> Result<(Vec<ToolContent>, Option<bool>), String> {
+        let result = self.call_tool(name.to_string(), input.clone())?;
+
+        log(&format!("Tool result: {:?}", result));
+        if let Some(err) = result.error {
+            log(&format!("Error calling tool: {}", err.message));
+            return Ok((
+                vec![ToolContent::Text {
+                    text: err.message.clone(),
+                }],
+                Some(true),
+            ));
+        }
+
+        log(&format!("Tool call result: {:?}", result.result));
+
+        let tool_result_value = result.result
+            .ok_or("No result field in tool response")?;
+
+        let tool_result = serde_json::from_value::<ToolCallResult>(tool_result_value)
+            .map_err(|e| format!("Failed to parse tool call result: {}", e))?;
+
+        Ok((tool_result.content, tool_result.is_error))
+    }
+
+    /// Name of the server that handles a tool
+    pub fn server_for_tool(&self, name: &str) -> Option<String> {
+        if tools::is_builtin_tool(self, name) {
+            return Some("chat-state".to_string());
+        }
+        self.settings
+            .mcp_servers
+            .iter()
+            .find(|mcp| mcp.has_tool(name))
+            .and_then(|mcp| mcp.label())
     }
 
     /// Get the list of tools from the MCP servers
     pub fn list_tools(&self) -> Result<Vec<Tool>, String> {
         log("Getting tool list from MCP servers");
 
-        let mut tools = Vec::new();
+        let mut tools = tools::builtin_tools(self);
 
         for mcp in &self.settings.mcp_servers {
             if let Some(ref actor_id) = mcp.actor_id {
@@ -631,44 +1461,14 @@ impl ChatState {
         }
     }
 
-    /// Get the list of models from the proxies
-    pub fn list_models(&self) -> Result<Vec<ModelInfo>, String> {
-        log("Getting model list from proxies");
-
-        let mut models = Vec::new();
-
-        for proxy in self.proxies.values() {
-            let response = proxy.send_to_proxy(ProxyRequest::ListModels);
-
-            match response {
-                Ok(ProxyResponse::ListModels { models: m }) => {
-                    log(&format!("Found models: {:?}", m));
-                    models.extend(m);
-                }
-                Ok(_) => {
-                    log("Unexpected response from proxy");
-                    return Err("Unexpected response from proxy".to_string());
-                }
-                Err(e) => {
-                    log(&format!("Error getting model list: {}", e));
-                    return Err(format!("Error getting model list: {}", e));
-                }
-            }
-        }
-
-        if models.is_empty() {
-            log("No models found");
-            return Err("No models found".to_string());
-        } else {
-            log(&format!("Found models: {:?}", models));
-            return Ok(models);
-        }
-    }
-
     /// Call a tool with the given name and arguments
     pub fn call_tool(&self, name: String, args: Value) -> Result<McpResponse, String> {
         log(&format!("Calling tool: {} with args: {:?}", name, args));
 
+        if tools::is_builtin_tool(self, &name) {
+            return tools::call_builtin_tool(self, &name, &args);
+        }
+
         // Check if the tool is available
         for mcp in &self.settings.mcp_servers {
             if mcp.has_tool(&name) {
@@ -679,43 +1479,65 @@ impl ChatState {
         Err(format!("Tool {} not found", name))
     }
 
-    /// Sends a request to the anthropic-proxy actor and returns the response
+    /// Sends a completion request for the given model to its provider's proxy
     pub fn generate_proxy_completion(
         &mut self,
-        proxy_name: &String,
-    ) -> Result<CompletionResponse, String> {
+        model_config: &ModelConfig,
+    ) -> Result<CompletionResponse, CompletionFailure> {
+        let proxy_name = &model_config.provider;
         log(&format!(
             "Generating completion from proxy actor: {}",
             proxy_name
         ));
 
         let messages = self
-            .get_chain()
+            .model_chain()
             .into_iter()
             .map(|m| m.entry.into())
             .collect::<Vec<_>>();
 
+        // Clamp to the limit of the model actually called, listing its
+        // provider's models first if the catalog has no entry yet
+        let max_tokens = match self.model_info(model_config) {
+            Some(info) => self.settings.max_tokens.min(info.max_tokens),
+            None => self.settings.max_tokens,
+        };
+
+        // Fallback and routed models may be on a provider that lacks some parameters
+        let mut params = match self.settings.provider_params.get(proxy_name) {
+            Some(overrides) => self.settings.completion_params.layered(overrides),
+            None => self.settings.completion_params.clone(),
+        };
+        let dropped = params.retain_supported(proxy_name);
+        if !dropped.is_empty() {
+            log(&format!(
+                "Provider {} does not support {:?}, leaving them out",
+                proxy_name, dropped
+            ));
+        }
+
         // Create the Anthropic request
-        let request = ProxyRequest::GenerateCompletion {
-            request: CompletionRequest {
-                model: self.settings.model_config.model.clone(),
-                messages,
-                temperature: self.settings.temperature,
-                max_tokens: self.settings.max_tokens,
-                disable_parallel_tool_use: None,
-                system: self.settings.system_prompt.clone(),
-                tools: self.get_tools()
-                    .map_err(|e| format!("Failed to get tools for completion: {}", e))?,
-                tool_choice: None,
-            },
+        let request = CompletionRequest {
+            model: model_config.model.clone(),
+            messages,
+            temperature: self.settings.temperature,
+            max_tokens,
+            disable_parallel_tool_use: params.disable_parallel_tool_use,
+            system: self
+                .render_system_prompt(None, model_config)
+                .map_err(CompletionFailure::Other)?,
+            tools: self.get_tools().map_err(|e| {
+                CompletionFailure::Other(format!("Failed to get tools for completion: {}", e))
+            })?,
+            tool_choice: params.tool_choice.clone(),
         };
 
         let response = self
             .proxies
-            .get(proxy_name)
-            .ok_or_else(|| format!("Proxy {} not found", proxy_name))?
-            .send_to_proxy(request)
-            .map_err(|e| format!("Failed to send request to proxy: {}", e))?;
+            .get_mut(proxy_name)
+            .ok_or_else(|| CompletionFailure::Other(format!("Proxy {} not found", proxy_name)))?
+            .send_to_proxy(ProxyRequest::GenerateCompletion { request })
+            .map_err(CompletionFailure::Transport)?;
 
         match response {
             ProxyResponse::Completion { completion } => {
@@ -724,19 +1546,30 @@ impl ChatState {
             }
             ProxyResponse::Error { error } => {
                 log(&format!("Error from proxy: {}", error));
-                Err(format!("Error from proxy: {}", error))
+                Err(CompletionFailure::Provider(error))
             }
-            _ => Err("Unexpected response from anthropic-proxy".to_string()),
+            _ => Err(CompletionFailure::Other(
+                "Unexpected response from anthropic-proxy".to_string(),
+            )),
         }
     }
 
     pub fn add_message(&mut self, chat_entry: ChatEntry) {
+        self.add_message_with_metadata(chat_entry, None);
+    }
+
+    pub fn add_message_with_metadata(
+        &mut self,
+        chat_entry: ChatEntry,
+        metadata: Option<MessageMetadata>,
+    ) {
         log("Adding message to conversation");
 
         let mut chat_msg = ChatMessage {
             id: None,
             parent_id: self.head.clone(),
             entry: chat_entry,
+            metadata,
         };
 
         // Serialize and store the message
@@ -760,6 +1593,7 @@ impl ChatState {
         chat_msg.id = Some(id.clone());
 
         self.messages.insert(id.clone(), chat_msg.clone());
+        self.record_head_move(&Some(id.clone()));
         self.head = Some(id.clone());
 
         if let Err(e) = self.store_head() {
@@ -793,10 +1627,12 @@ impl ChatState {
             }
         }
 
+        self.record_head_move(&head);
         self.head = head.clone();
         if let Err(e) = self.store_head() {
             log(&format!("Failed to store head: {}", e));
         }
+        self.broadcast(&ChatStateResponse::Head { head });
         Ok(())
     }
 
@@ -818,7 +1654,7 @@ impl ChatState {
             }
         };
 
-        let chat_msg = match serde_json::to_vec(&ChatStateResponse::ChatMessage { message: chat_msg }) {
+        let chat_msg = match serde_json::to_vec(&ChatStateResponse::ChatMessage { message: Box::new(chat_msg) }) {
             Ok(msg) => msg,
             Err(e) => {
                 log(&format!("Failed to serialize chat message: {}", e));
@@ -845,14 +1681,32 @@ impl ChatState {
         }
     }
 
+    /// Send a response to every subscription channel
+    pub fn broadcast(&self, response: &ChatStateResponse) {
+        let msg = match serde_json::to_vec(response) {
+            Ok(msg) => msg,
+            Err(e) => {
+                log(&format!("Failed to serialize channel message: {}", e));
+                return;
+            }
+        };
+
+        for channel_id in &self.subscription_channels {
+            if let Err(e) = message_server_host::send_on_channel(channel_id, &msg) {
+                log(&format!("Failed to notify channel {}: {}", channel_id, e));
+            }
+        }
+    }
+
     pub fn get_chain(&mut self) -> Vec<ChatMessage> {
         let mut chain = Vec::new();
 
         let mut current_id = self.head.clone();
         while let Some(id) = current_id {
-            if let Ok(Some(message)) = self.get_message(&id) {
-                chain.push(message.clone());
+            if let Ok(Some(mut message)) = self.get_message(&id) {
                 current_id = message.parent_id.clone();
+                message.id = Some(id);
+                chain.push(message);
             } else {
                 break;
             }
@@ -860,12 +1714,25 @@ impl ChatState {
 
         chain.reverse();
 
-        chain
+        self.visible_chain(chain)
+    }
+
+    /// The chain as the model sees it: local command replies are left out
+    pub fn model_chain(&mut self) -> Vec<ChatMessage> {
+        self.get_chain()
+            .into_iter()
+            .filter(|m| !matches!(m.entry, ChatEntry::Local(_)))
+            .collect()
     }
 
     pub fn get_message(&mut self, id: &str) -> Result<Option<ChatMessage>, String> {
         log(&format!("Getting message with ID: {}", id));
 
+        if self.moderation.redacted.contains(id) {
+            log(&format!("Message {} was redacted", id));
+            return Ok(None);
+        }
+
         // If the message is not found in our messages, check the store
         match self.messages.get(id) {
             Some(message) => Ok(Some(message.clone())),
@@ -901,11 +1768,20 @@ impl ChatState {
     }
 
     /// Update conversation settings
-    pub fn update_settings(&mut self, settings: ConversationSettings) {
+    pub fn update_settings(&mut self, mut settings: ConversationSettings) -> Result<(), String> {
+        // A title set by hand, even to the default, is never replaced automatically
+        settings.title_settled |=
+            self.settings.title_settled || settings.title != self.settings.title;
+
+        settings.mcp_servers = self.reconcile_mcp_servers(settings.mcp_servers);
         self.settings = settings;
 
         log(&format!("Updated settings: {:?}", self.settings));
 
+        if let Err(e) = self.sync_proxies() {
+            log(&format!("Failed to sync provider proxies: {}", e));
+        }
+
         // Start or restart MCP servers with new configuration
         if let Err(e) = self.start_mcp_servers() {
             log(&format!("Failed to start MCP servers: {}", e));
@@ -914,6 +1790,8 @@ impl ChatState {
         if let Err(e) = self.store_settings() {
             log(&format!("Failed to store conversation settings: {}", e));
         }
+
+        Ok(())
     }
 
     /// Add channel to subscriptions (called automatically)
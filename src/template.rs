@@ -0,0 +1,154 @@
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::timing;
+use crate::state::{ChatState, ModelConfig};
+use std::collections::HashMap;
+
+/// Replace `{{name}}` placeholders with their values. Unknown names are left as is.
+pub fn render_template(template: &str, variables: &HashMap<String, String>) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        let after_open = &rest[start + 2..];
+        let end = match after_open.find("}}") {
+            Some(end) => end,
+            None => break,
+        };
+
+        rendered.push_str(&rest[..start]);
+        let name = after_open[..end].trim();
+        match variables.get(name) {
+            Some(value) => rendered.push_str(value),
+            None => {
+                log(&format!("Unknown system prompt variable: {}", name));
+                rendered.push_str(&rest[start..start + 2 + end + 2]);
+            }
+        }
+        rest = &after_open[end + 2..];
+    }
+
+    rendered.push_str(rest);
+    rendered
+}
+
+/// Whether `name` can be used as a template variable
+pub fn is_valid_variable_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// UTC calendar date (`YYYY-MM-DD`) for milliseconds since the Unix epoch
+fn format_date(now_ms: u64) -> String {
+    // Howard Hinnant's days-to-civil algorithm
+    let days = (now_ms / 86_400_000) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+impl ChatState {
+    /// Variables available to the system prompt. The built-in `date`, `title`,
+    /// `model` and `tools` take precedence over `settings.prompt_variables`.
+    fn prompt_variables(&self, model: &ModelConfig) -> Result<HashMap<String, String>, String> {
+        let mut variables = self.settings.prompt_variables.clone();
+
+        let tools = self
+            .get_tools()?
+            .unwrap_or_default()
+            .iter()
+            .map(|tool| match &tool.description {
+                Some(description) => format!("- {}: {}", tool.name, description),
+                None => format!("- {}", tool.name),
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        variables.insert("date".to_string(), format_date(timing::now()));
+        variables.insert("title".to_string(), self.settings.title.clone());
+        variables.insert("model".to_string(), model.model.clone());
+        variables.insert("tools".to_string(), tools);
+        Ok(variables)
+    }
+
+    /// Render `template`, or the configured system prompt when none is given,
+    /// for a completion from `model`
+    pub fn render_system_prompt(
+        &self,
+        template: Option<&str>,
+        model: &ModelConfig,
+    ) -> Result<Option<String>, String> {
+        let template = match template.or(self.settings.system_prompt.as_deref()) {
+            Some(template) => template,
+            None => return Ok(None),
+        };
+
+        if !template.contains("{{") {
+            return Ok(Some(template.to_string()));
+        }
+
+        Ok(Some(render_template(
+            template,
+            &self.prompt_variables(model)?,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_date, is_valid_variable_name, render_template};
+    use std::collections::HashMap;
+
+    fn variables() -> HashMap<String, String> {
+        HashMap::from([
+            ("model".to_string(), "claude".to_string()),
+            ("date".to_string(), "2026-01-02".to_string()),
+        ])
+    }
+
+    #[test]
+    fn replaces_known_variables() {
+        assert_eq!(
+            render_template("You are {{model}}. Today is {{ date }}.", &variables()),
+            "You are claude. Today is 2026-01-02."
+        );
+        assert_eq!(
+            render_template("{{model}}{{model}}", &variables()),
+            "claudeclaude"
+        );
+    }
+
+    #[test]
+    fn leaves_text_without_placeholders_alone() {
+        assert_eq!(
+            render_template("plain {text}", &variables()),
+            "plain {text}"
+        );
+        assert_eq!(
+            render_template("unclosed {{model", &variables()),
+            "unclosed {{model"
+        );
+    }
+
+    #[test]
+    fn checks_variable_names() {
+        assert!(is_valid_variable_name("user_name2"));
+        assert!(!is_valid_variable_name(""));
+        assert!(!is_valid_variable_name("user-name"));
+        assert!(!is_valid_variable_name("a b"));
+    }
+
+    #[test]
+    fn formats_utc_dates() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(951_782_400_000), "2000-02-29");
+        assert_eq!(format_date(1_709_164_800_000), "2024-02-29");
+        assert_eq!(format_date(1_767_225_599_999), "2025-12-31");
+        assert_eq!(format_date(1_767_225_600_000), "2026-01-01");
+    }
+}
@@ -0,0 +1,162 @@
+use crate::bindings::theater::simple::runtime::log;
+use crate::protocol::ChatStateResponse;
+use crate::state::{ChatState, DEFAULT_TITLE};
+use crate::tools::render_entry;
+use genai_types::messages::Role;
+use genai_types::{CompletionRequest, Message, MessageContent, ProxyRequest, ProxyResponse};
+
+const TITLE_PROMPT: &str = "Write a title of at most six words for the conversation below. \
+Reply with the title only, without quotes or a trailing period.";
+const TITLE_MAX_TOKENS: u32 = 32;
+
+/// Characters of the conversation sent to the title model
+const TITLE_TRANSCRIPT_CHARS: usize = 4000;
+const TITLE_MAX_CHARS: usize = 80;
+
+/// First non-empty line of the model's reply, without decoration
+fn clean_title(text: &str) -> Option<String> {
+    let line = text.lines().map(str::trim).find(|line| !line.is_empty())?;
+    let title = line
+        .trim_matches(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '*' | '#'))
+        .trim_end_matches('.')
+        .trim();
+    if title.is_empty() {
+        return None;
+    }
+    Some(title.chars().take(TITLE_MAX_CHARS).collect())
+}
+
+impl ChatState {
+    /// Generate a title once the first exchange is done, unless the
+    /// conversation already has one or automatic titles are off. Only one
+    /// attempt is made, whether or not it succeeds.
+    pub fn maybe_generate_title(&mut self) {
+        // Settings stored before `title_settled` existed rely on the title check
+        if !self.settings.auto_title
+            || self.settings.title_settled
+            || self.settings.title != DEFAULT_TITLE
+        {
+            return;
+        }
+
+        self.settings.title_settled = true;
+        if let Err(e) = self.regenerate_title() {
+            log(&format!("Failed to generate conversation title: {}", e));
+            if let Err(e) = self.store_settings() {
+                log(&format!("Failed to store conversation settings: {}", e));
+            }
+        }
+    }
+
+    /// Ask `title_model` (or the conversation's model) for a title, store it
+    /// in settings and notify subscribers
+    pub fn regenerate_title(&mut self) -> Result<String, String> {
+        log("Generating conversation title");
+
+        let transcript: String = self
+            .model_chain()
+            .iter()
+            .map(|m| {
+                let (role, text) = render_entry(&m.entry);
+                format!("{}: {}", role, text)
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n")
+            .chars()
+            .take(TITLE_TRANSCRIPT_CHARS)
+            .collect();
+        if transcript.is_empty() {
+            return Err("Conversation is empty".to_string());
+        }
+
+        let model_config = self
+            .settings
+            .title_model
+            .clone()
+            .unwrap_or_else(|| self.settings.model_config.clone());
+        let request = ProxyRequest::GenerateCompletion {
+            request: CompletionRequest {
+                model: model_config.model.clone(),
+                messages: vec![Message {
+                    role: Role::User,
+                    content: vec![MessageContent::Text { text: transcript }],
+                }],
+                max_tokens: TITLE_MAX_TOKENS,
+                temperature: None,
+                system: Some(TITLE_PROMPT.to_string()),
+                tools: None,
+                tool_choice: None,
+                disable_parallel_tool_use: None,
+            },
+        };
+
+        let response = self
+            .proxies
+            .get_mut(&model_config.provider)
+            .ok_or_else(|| format!("Proxy {} not found", model_config.provider))?
+            .send_to_proxy(request)?;
+
+        let text = match response {
+            ProxyResponse::Completion { completion } => completion
+                .content
+                .iter()
+                .filter_map(|content| match content {
+                    MessageContent::Text { text } => Some(text.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join(""),
+            ProxyResponse::Error { error } => return Err(format!("Error from proxy: {}", error)),
+            _ => return Err("Unexpected response from proxy".to_string()),
+        };
+        let title = clean_title(&text).ok_or("Model returned an empty title")?;
+
+        log(&format!("Conversation title: {}", title));
+        self.settings.title = title.clone();
+        self.settings.title_settled = true;
+        self.store_settings()?;
+        self.broadcast(&ChatStateResponse::TitleChanged {
+            title: title.clone(),
+        });
+
+        Ok(title)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{clean_title, TITLE_MAX_CHARS};
+
+    #[test]
+    fn strips_quotes_markdown_and_trailing_period() {
+        assert_eq!(
+            clean_title("\"Planning a trip to Lisbon.\""),
+            Some("Planning a trip to Lisbon".to_string())
+        );
+        assert_eq!(
+            clean_title("## **Rust borrow checker**"),
+            Some("Rust borrow checker".to_string())
+        );
+    }
+
+    #[test]
+    fn uses_the_first_non_empty_line() {
+        assert_eq!(
+            clean_title("\n  \nDebugging flaky tests\nBecause the conversation..."),
+            Some("Debugging flaky tests".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_empty_titles() {
+        assert_eq!(clean_title(""), None);
+        assert_eq!(clean_title("  \n\n"), None);
+        assert_eq!(clean_title("\"...\""), None);
+    }
+
+    #[test]
+    fn truncates_long_titles_on_char_boundaries() {
+        let title = clean_title(&"é".repeat(TITLE_MAX_CHARS + 10)).unwrap();
+        assert_eq!(title.chars().count(), TITLE_MAX_CHARS);
+    }
+}
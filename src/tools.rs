@@ -0,0 +1,401 @@
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store::{self, ContentRef};
+use crate::protocol::McpResponse;
+use crate::state::{ChatEntry, ChatMessage, ChatState};
+use genai_types::messages::Role;
+use genai_types::{Message, MessageContent};
+use mcp_protocol::tool::{Tool, ToolCallResult, ToolContent};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+
+/// Built-in tool that pages through tool results too large to send inline
+pub const READ_TOOL_RESULT: &str = "read_tool_result";
+
+/// Built-in tools that let the model look back through the conversation
+pub const SEARCH_HISTORY: &str = "search_history";
+pub const GET_MESSAGE: &str = "get_message";
+pub const READ_NOTE: &str = "read_note";
+
+const DEFAULT_SEARCH_RESULTS: usize = 10;
+const SNIPPET_CONTEXT: usize = 120;
+
+/// Tools handled by chat-state itself rather than by an MCP server
+pub fn builtin_tools(state: &ChatState) -> Vec<Tool> {
+    let mut tools = Vec::new();
+
+    if let Some(limit) = state.settings.tool_result_limit {
+        tools.push(Tool {
+            name: READ_TOOL_RESULT.to_string(),
+            description: Some(format!(
+                "Read part of a tool result that was too large to include inline. \
+                 Pass the reference from the truncation notice and a byte offset; \
+                 at most {} bytes are returned per call.",
+                limit
+            )),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "reference": {
+                        "type": "string",
+                        "description": "Reference of the stored tool result"
+                    },
+                    "offset": {
+                        "type": "integer",
+                        "description": "Byte offset to start reading from"
+                    },
+                    "length": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "description": "Maximum number of bytes to read"
+                    }
+                },
+                "required": ["reference"]
+            }),
+            annotations: None,
+        });
+    }
+
+    if state.settings.introspection_tools {
+        tools.push(Tool {
+            name: SEARCH_HISTORY.to_string(),
+            description: Some(
+                "Search earlier messages in this conversation for a case-insensitive \
+                 substring. Returns matching message ids with a snippet around each match."
+                    .to_string(),
+            ),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "query": {
+                        "type": "string",
+                        "description": "Text to search for"
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "description": "Maximum number of matches to return"
+                    }
+                },
+                "required": ["query"]
+            }),
+            annotations: None,
+        });
+        tools.push(Tool {
+            name: GET_MESSAGE.to_string(),
+            description: Some(
+                "Fetch the full content of an earlier message in this conversation by id."
+                    .to_string(),
+            ),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "message_id": {
+                        "type": "string",
+                        "description": "Id of the message to fetch"
+                    }
+                },
+                "required": ["message_id"]
+            }),
+            annotations: None,
+        });
+
+        if state.settings.pinned_note.is_some() {
+            tools.push(Tool {
+                name: READ_NOTE.to_string(),
+                description: Some("Read the note pinned to this conversation.".to_string()),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {}
+                }),
+                annotations: None,
+            });
+        }
+    }
+
+    tools
+}
+
+pub fn is_builtin_tool(state: &ChatState, name: &str) -> bool {
+    builtin_tools(state).iter().any(|t| t.name == name)
+}
+
+/// Wrap built-in tool output in the same response shape MCP servers return
+fn tool_response(name: &str, content: Vec<ToolContent>, is_error: bool) -> McpResponse {
+    let result = ToolCallResult {
+        content,
+        is_error: if is_error { Some(true) } else { None },
+    };
+
+    McpResponse {
+        jsonrpc: "2.0".to_string(),
+        id: name.to_string(),
+        result: serde_json::to_value(result).ok(),
+        error: None,
+    }
+}
+
+/// Call a built-in tool
+pub fn call_builtin_tool(
+    state: &ChatState,
+    name: &str,
+    args: &Value,
+) -> Result<McpResponse, String> {
+    log(&format!(
+        "Calling built-in tool: {} with args: {:?}",
+        name, args
+    ));
+
+    let result = match name {
+        READ_TOOL_RESULT => read_tool_result(state, args),
+        SEARCH_HISTORY => search_history(state, args),
+        GET_MESSAGE => get_message(state, args),
+        READ_NOTE => state
+            .settings
+            .pinned_note
+            .clone()
+            .ok_or_else(|| "No note is pinned to this conversation".to_string()),
+        _ => Err(format!("Built-in tool {} not found", name)),
+    };
+
+    Ok(match result {
+        Ok(text) => tool_response(name, vec![ToolContent::Text { text }], false),
+        Err(e) => tool_response(name, vec![ToolContent::Text { text: e }], true),
+    })
+}
+
+fn read_tool_result(state: &ChatState, args: &Value) -> Result<String, String> {
+    let reference = args
+        .get("reference")
+        .and_then(Value::as_str)
+        .ok_or("Missing 'reference' argument")?;
+    // Only results this conversation truncated may be read, never other blobs in the store
+    if !state.stored_tool_results.contains(reference) {
+        return Err(format!("Unknown tool result reference {}", reference));
+    }
+    let offset = args.get("offset").and_then(Value::as_u64).unwrap_or(0) as usize;
+    let limit = state.settings.tool_result_limit.unwrap_or(usize::MAX);
+    let length = args
+        .get("length")
+        .and_then(Value::as_u64)
+        .map(|l| (l as usize).min(limit))
+        .unwrap_or(limit);
+    if length == 0 {
+        return Err("The 'length' argument must be greater than 0".to_string());
+    }
+
+    let bytes = store::get(
+        &state.store_id,
+        &ContentRef {
+            hash: reference.to_string(),
+        },
+    )
+    .map_err(|e| format!("Failed to read stored tool result {}: {}", reference, e))?;
+    let text = String::from_utf8(bytes)
+        .map_err(|e| format!("Stored tool result {} is not text: {}", reference, e))?;
+
+    if offset >= text.len() {
+        return Err(format!(
+            "Offset {} is past the end of the result ({} bytes)",
+            offset,
+            text.len()
+        ));
+    }
+
+    let start = floor_char_boundary(&text, offset);
+    let end = floor_char_boundary(&text, start.saturating_add(length));
+    let remaining = text.len() - end;
+
+    let mut page = text[start..end].to_string();
+    if remaining > 0 {
+        page.push_str(&format!(
+            "\n\n[{} more bytes; continue with offset {}]",
+            remaining, end
+        ));
+    }
+
+    Ok(page)
+}
+
+/// Look up a message this conversation has added or loaded. Other blobs in the
+/// store are never read, even when the id is a valid hash.
+fn load_message(state: &ChatState, id: &str) -> Option<ChatMessage> {
+    if state.moderation.redacted.contains(id) {
+        return None;
+    }
+    state.messages.get(id).cloned()
+}
+
+/// Role and text of a chat entry, with tool calls and results rendered inline
+pub fn render_entry(entry: &ChatEntry) -> (String, String) {
+    let message: Message = entry.clone().into();
+    let role = match message.role {
+        Role::User => "user",
+        Role::Assistant => "assistant",
+        Role::System => "system",
+    };
+
+    let text = message
+        .content
+        .iter()
+        .map(|content| match content {
+            MessageContent::Text { text } => text.clone(),
+            MessageContent::ToolUse { name, input, .. } => {
+                format!("[tool call {}: {}]", name, input)
+            }
+            MessageContent::ToolResult { content, .. } => content
+                .iter()
+                .map(|c| match c {
+                    ToolContent::Text { text } => text.clone(),
+                    other => serde_json::to_string(other).unwrap_or_default(),
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    (role.to_string(), text)
+}
+
+fn search_history(state: &ChatState, args: &Value) -> Result<String, String> {
+    let query = args
+        .get("query")
+        .and_then(Value::as_str)
+        .ok_or("Missing 'query' argument")?
+        .to_lowercase();
+    if query.is_empty() {
+        return Err("The 'query' argument must not be empty".to_string());
+    }
+    let limit = args
+        .get("limit")
+        .and_then(Value::as_u64)
+        .map(|l| l as usize)
+        .unwrap_or(DEFAULT_SEARCH_RESULTS);
+
+    // The head is the completion that asked for this search, so matching it
+    // would only echo the query back
+    let mut chain = Vec::new();
+    let mut current_id = state
+        .head
+        .as_deref()
+        .and_then(|head| load_message(state, head))
+        .and_then(|head| head.parent_id);
+    while let Some(id) = current_id {
+        let mut message = match load_message(state, &id) {
+            Some(message) => message,
+            None => break,
+        };
+        current_id = message.parent_id.clone();
+        message.id = Some(id);
+        chain.push(message);
+    }
+    chain.reverse();
+
+    let mut matches = Vec::new();
+    // Local command replies are never shown to the model
+    let visible = state
+        .visible_chain(chain)
+        .into_iter()
+        .filter(|m| !matches!(m.entry, ChatEntry::Local(_)));
+    for message in visible.rev() {
+        let id = message.id.as_deref().unwrap_or_default();
+        let (role, text) = render_entry(&message.entry);
+        // Lowercasing can change byte lengths, so only use positions from the
+        // lowered text when it lines up with the original
+        let lowered = text.to_lowercase();
+        if let Some(pos) = lowered.find(&query) {
+            let pos = if lowered.len() == text.len() { pos } else { 0 };
+            let start = floor_char_boundary(&text, pos.saturating_sub(SNIPPET_CONTEXT));
+            let end = floor_char_boundary(&text, pos + query.len() + SNIPPET_CONTEXT);
+            matches.push(format!("[{}] {}: ...{}...", id, role, &text[start..end]));
+            if matches.len() >= limit {
+                break;
+            }
+        }
+    }
+
+    if matches.is_empty() {
+        Ok(format!("No messages match '{}'", query))
+    } else {
+        Ok(matches.join("\n\n"))
+    }
+}
+
+fn get_message(state: &ChatState, args: &Value) -> Result<String, String> {
+    let message_id = args
+        .get("message_id")
+        .and_then(Value::as_str)
+        .ok_or("Missing 'message_id' argument")?;
+
+    let message = load_message(state, message_id)
+        .filter(|m| state.is_visible(message_id) && !matches!(m.entry, ChatEntry::Local(_)))
+        .ok_or_else(|| format!("Message {} not found", message_id))?;
+    let (role, text) = render_entry(&message.entry);
+
+    Ok(format!(
+        "id: {}\nparent: {}\nrole: {}\n\n{}",
+        message_id,
+        message.parent_id.as_deref().unwrap_or("none"),
+        role,
+        text
+    ))
+}
+
+/// Replace any text content larger than `limit` bytes with a preview and a store
+/// reference, recording the reference in `stored`. Other content passes through.
+pub fn truncate_tool_content(
+    store_id: &str,
+    content: Vec<ToolContent>,
+    limit: usize,
+    stored: &mut HashSet<String>,
+) -> Vec<ToolContent> {
+    content
+        .into_iter()
+        .map(|item| {
+            let full = match &item {
+                ToolContent::Text { text } if text.len() > limit => text,
+                _ => return item,
+            };
+
+            let content_ref = match store::store(store_id, full.as_bytes()) {
+                Ok(content_ref) => content_ref,
+                Err(e) => {
+                    log(&format!("Failed to store oversized tool result: {}", e));
+                    return item;
+                }
+            };
+            stored.insert(content_ref.hash.clone());
+
+            log(&format!(
+                "Truncated tool result of {} bytes, stored as {}",
+                full.len(),
+                content_ref.hash
+            ));
+
+            let preview_end = floor_char_boundary(full, limit);
+            ToolContent::Text {
+                text: format!(
+                    "{}\n\n[Result truncated: showing {} of {} bytes. The full result is stored \
+                     with reference {}. Call {} with this reference and offset {} to read more.]",
+                    &full[..preview_end],
+                    preview_end,
+                    full.len(),
+                    content_ref.hash,
+                    READ_TOOL_RESULT,
+                    preview_end
+                ),
+            }
+        })
+        .collect()
+}
+
+/// Largest char boundary in `s` that is not past `index`
+fn floor_char_boundary(s: &str, index: usize) -> usize {
+    if index >= s.len() {
+        return s.len();
+    }
+    let mut i = index;
+    while !s.is_char_boundary(i) {
+        i -= 1;
+    }
+    i
+}
@@ -0,0 +1,58 @@
+use crate::bindings::theater::simple::runtime::log;
+use crate::protocol::ChatStateResponse;
+use crate::state::ChatState;
+use serde::{Deserialize, Serialize};
+
+/// Oldest heads are dropped beyond this many undo steps
+const MAX_UNDO_DEPTH: usize = 100;
+
+/// Heads the conversation moved away from, most recent last, and the heads
+/// stepped back over by undo. Persisted with the rest of the actor state.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct HeadHistory {
+    pub undo: Vec<Option<String>>,
+    pub redo: Vec<Option<String>>,
+}
+
+impl ChatState {
+    /// Push the current head before it moves to `new_head`. A new move
+    /// discards anything that could have been redone.
+    pub fn record_head_move(&mut self, new_head: &Option<String>) {
+        if self.head == *new_head {
+            return;
+        }
+
+        self.head_history.undo.push(self.head.clone());
+        if self.head_history.undo.len() > MAX_UNDO_DEPTH {
+            self.head_history.undo.remove(0);
+        }
+        self.head_history.redo.clear();
+    }
+
+    fn restore_head(&mut self, head: Option<String>) -> Option<String> {
+        self.head = head;
+        if let Err(e) = self.store_head() {
+            log(&format!("Failed to store head: {}", e));
+        }
+        self.broadcast(&ChatStateResponse::Head {
+            head: self.head.clone(),
+        });
+        self.head.clone()
+    }
+
+    /// Move the head back to where it was before its last move
+    pub fn undo_head(&mut self) -> Result<Option<String>, String> {
+        let previous = self.head_history.undo.pop().ok_or("Nothing to undo")?;
+        log(&format!("Undoing head move back to {:?}", previous));
+        self.head_history.redo.push(self.head.clone());
+        Ok(self.restore_head(previous))
+    }
+
+    /// Reapply the last undone head move
+    pub fn redo_head(&mut self) -> Result<Option<String>, String> {
+        let next = self.head_history.redo.pop().ok_or("Nothing to redo")?;
+        log(&format!("Redoing head move to {:?}", next));
+        self.head_history.undo.push(self.head.clone());
+        Ok(self.restore_head(next))
+    }
+}
@@ -0,0 +1,314 @@
+use crate::bindings::theater::simple::runtime::log;
+use crate::params::CompletionParams;
+use crate::protocol::{
+    create_error_response, create_error_response_with_details, ChatStateResponse,
+};
+use crate::state::{ChatState, ConversationSettings, ModelConfig};
+use crate::template::is_valid_variable_name;
+use genai_types::ToolChoice;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt::Display;
+
+/// Invalid settings fields, keyed by path (e.g. `mcp_servers[0].command`)
+pub type SettingsErrors = HashMap<String, String>;
+
+/// Check settings for values that would only fail later, on the next
+/// completion or server start. Every invalid field is reported.
+pub fn validate_settings(settings: &ConversationSettings) -> Result<(), SettingsErrors> {
+    let mut errors = SettingsErrors::new();
+
+    if let Some(temperature) = settings.temperature {
+        if !(0.0..=1.0).contains(&temperature) {
+            errors.insert(
+                "temperature".to_string(),
+                format!("must be between 0 and 1, got {}", temperature),
+            );
+        }
+    }
+
+    if settings.max_tokens == 0 {
+        errors.insert(
+            "max_tokens".to_string(),
+            "must be greater than 0".to_string(),
+        );
+    }
+
+    if settings.tool_result_limit == Some(0) {
+        errors.insert(
+            "tool_result_limit".to_string(),
+            "must be greater than 0".to_string(),
+        );
+    }
+
+    for name in settings.prompt_variables.keys() {
+        if !is_valid_variable_name(name) {
+            errors.insert(
+                format!("prompt_variables.{}", name),
+                "names may only contain letters, digits and underscores".to_string(),
+            );
+        }
+    }
+
+    let mut provider_names = HashSet::new();
+    for (i, provider) in settings.providers.iter().enumerate() {
+        if provider.name.trim().is_empty() {
+            errors.insert(
+                format!("providers[{}].name", i),
+                "must not be empty".to_string(),
+            );
+        } else if !provider_names.insert(provider.name.as_str()) {
+            errors.insert(
+                format!("providers[{}].name", i),
+                format!("duplicate provider {}", provider.name),
+            );
+        }
+        if provider.manifest_path.trim().is_empty() {
+            errors.insert(
+                format!("providers[{}].manifest_path", i),
+                "must not be empty".to_string(),
+            );
+        }
+    }
+
+    check_model_config(
+        &mut errors,
+        "model_config",
+        &settings.model_config,
+        &provider_names,
+    );
+    for (i, model_config) in settings.fallback_models.iter().enumerate() {
+        check_model_config(
+            &mut errors,
+            &format!("fallback_models[{}]", i),
+            model_config,
+            &provider_names,
+        );
+    }
+    if let Some(title_model) = &settings.title_model {
+        check_model_config(&mut errors, "title_model", title_model, &provider_names);
+    }
+    for (i, rule) in settings.routing_rules.iter().enumerate() {
+        check_model_config(
+            &mut errors,
+            &format!("routing_rules[{}].model_config", i),
+            &rule.model_config,
+            &provider_names,
+        );
+    }
+
+    check_params(&mut errors, "completion_params", &settings.completion_params);
+    for (provider, params) in &settings.provider_params {
+        let path = format!("provider_params.{}", provider);
+        if !provider_names.contains(provider.as_str()) {
+            errors.insert(path.clone(), format!("unknown provider {}", provider));
+        }
+        check_params(&mut errors, &path, params);
+        for param in params.unsupported_by(provider) {
+            errors.insert(
+                format!("{}.{}", path, param),
+                format!("not supported by provider {}", provider),
+            );
+        }
+    }
+    check_params_supported(&mut errors, settings);
+
+    let retry = &settings.completion_retry;
+    if retry.max_backoff_ms < retry.initial_backoff_ms {
+        errors.insert(
+            "completion_retry.max_backoff_ms".to_string(),
+            "must not be less than initial_backoff_ms".to_string(),
+        );
+    }
+
+    let mut server_names = HashSet::new();
+    for (i, server) in settings.mcp_servers.iter().enumerate() {
+        let path = format!("mcp_servers[{}]", i);
+
+        if let Some(name) = &server.name {
+            if name.trim().is_empty() {
+                errors.insert(format!("{}.name", path), "must not be empty".to_string());
+            } else if !server_names.insert(name.as_str()) {
+                errors.insert(
+                    format!("{}.name", path),
+                    format!("duplicate server {}", name),
+                );
+            }
+        }
+
+        for (field, message) in server.config.problems() {
+            errors.insert(format!("{}.{}", path, field), message);
+        }
+
+        if server.timeout_ms == Some(0) {
+            errors.insert(
+                format!("{}.timeout_ms", path),
+                "must be greater than 0".to_string(),
+            );
+        }
+        for (tool, timeout) in server.tool_timeouts.iter().flatten() {
+            if *timeout == 0 {
+                errors.insert(
+                    format!("{}.tool_timeouts.{}", path, tool),
+                    "must be greater than 0".to_string(),
+                );
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+fn check_model_config(
+    errors: &mut SettingsErrors,
+    path: &str,
+    model_config: &ModelConfig,
+    providers: &HashSet<&str>,
+) {
+    if model_config.model.trim().is_empty() {
+        errors.insert(format!("{}.model", path), "must not be empty".to_string());
+    }
+    if !providers.contains(model_config.provider.as_str()) {
+        errors.insert(
+            format!("{}.provider", path),
+            format!("unknown provider {}", model_config.provider),
+        );
+    }
+}
+
+fn check_params(errors: &mut SettingsErrors, path: &str, params: &CompletionParams) {
+    if let Some(ToolChoice::Tool { name }) = &params.tool_choice {
+        if name.trim().is_empty() {
+            errors.insert(
+                format!("{}.tool_choice.name", path),
+                "must not be empty".to_string(),
+            );
+        }
+    }
+}
+
+/// Check `completion_params` against every provider a completion can be sent
+/// to: the primary model's, the fallbacks' and the routing rules'. Parameters
+/// a provider's own `provider_params` set are reported there instead.
+fn check_params_supported(errors: &mut SettingsErrors, settings: &ConversationSettings) {
+    let providers: BTreeSet<&str> = std::iter::once(&settings.model_config)
+        .chain(&settings.fallback_models)
+        .chain(settings.routing_rules.iter().map(|rule| &rule.model_config))
+        .map(|model_config| model_config.provider.as_str())
+        .collect();
+
+    let mut unsupported: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for provider in providers {
+        let overrides = settings.provider_params.get(provider);
+        let params = match overrides {
+            Some(overrides) => settings.completion_params.layered(overrides),
+            None => settings.completion_params.clone(),
+        };
+        for param in params.unsupported_by(provider) {
+            if !overrides.is_some_and(|o| o.set_params().contains(&param)) {
+                unsupported.entry(param).or_default().push(provider);
+            }
+        }
+    }
+
+    for (param, providers) in unsupported {
+        errors.insert(
+            format!("completion_params.{}", param),
+            format!("not supported by provider {}", providers.join(", ")),
+        );
+    }
+}
+
+/// One-line summary of settings errors, sorted by field
+pub fn describe_errors(errors: &SettingsErrors) -> String {
+    let mut fields: Vec<String> = errors
+        .iter()
+        .map(|(field, message)| format!("{}: {}", field, message))
+        .collect();
+    fields.sort();
+    format!("Invalid settings: {}", fields.join("; "))
+}
+
+/// Why new settings were not applied
+#[derive(Debug)]
+pub enum SettingsError {
+    /// A settings patch could not be applied
+    Patch(String),
+
+    /// A previous settings version could not be loaded
+    Rollback(String),
+
+    /// A settings preset could not be loaded or saved
+    Preset(String),
+
+    /// The settings failed validation
+    Invalid(SettingsErrors),
+
+    /// A provider to change was not found
+    Provider(String),
+
+    /// The settings were valid but could not be made current
+    Update(String),
+}
+
+impl Display for SettingsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SettingsError::Invalid(errors) => write!(f, "{}", describe_errors(errors)),
+            SettingsError::Patch(e)
+            | SettingsError::Rollback(e)
+            | SettingsError::Preset(e)
+            | SettingsError::Provider(e)
+            | SettingsError::Update(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl SettingsError {
+    pub fn into_response(self) -> ChatStateResponse {
+        match self {
+            SettingsError::Patch(e) => {
+                log(&format!("Failed to patch settings: {}", e));
+                create_error_response("invalid_patch", &e)
+            }
+            SettingsError::Rollback(e) => {
+                log(&format!("Failed to roll back settings: {}", e));
+                create_error_response("rollback_error", &e)
+            }
+            SettingsError::Preset(e) => {
+                log(&format!("Settings preset failed: {}", e));
+                create_error_response("preset_error", &e)
+            }
+            SettingsError::Invalid(errors) => {
+                let message = describe_errors(&errors);
+                log(&message);
+                create_error_response_with_details("invalid_settings", &message, errors)
+            }
+            SettingsError::Provider(e) => {
+                log(&format!("Failed to change providers: {}", e));
+                create_error_response("provider_error", &e)
+            }
+            SettingsError::Update(e) => {
+                log(&format!("Failed to update settings: {}", e));
+                create_error_response("settings_error", &e)
+            }
+        }
+    }
+}
+
+/// Validate settings, check their models against the catalog and make them current
+pub fn apply_settings(
+    chat_state: &mut ChatState,
+    mut settings: ConversationSettings,
+) -> Result<(), SettingsError> {
+    validate_settings(&settings).map_err(SettingsError::Invalid)?;
+    chat_state
+        .check_models_against_catalog(&mut settings)
+        .map_err(SettingsError::Invalid)?;
+    chat_state
+        .update_settings(settings)
+        .map_err(SettingsError::Update)
+}